@@ -0,0 +1,291 @@
+-- Upgrades the tables of an existing database in place, scheme.sql drops and
+-- recreates them so it's only meant for new deployments.
+-- Every statement can be run again on an already upgraded database.
+
+-- the blocks of the bets stored before the reorg tracking are unknown
+ALTER TABLE Bet ADD COLUMN IF NOT EXISTS block_number BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE Bet ADD COLUMN IF NOT EXISTS block_hash character(66) NOT NULL
+    DEFAULT '0x0000000000000000000000000000000000000000000000000000000000000000';
+ALTER TABLE Bet ALTER COLUMN block_number DROP DEFAULT;
+ALTER TABLE Bet ALTER COLUMN block_hash DROP DEFAULT;
+ALTER TABLE Bet ADD COLUMN IF NOT EXISTS log_index BIGINT NOT NULL DEFAULT 0;
+
+-- bets are keyed by their event log instead of the transaction,
+-- the repeated logs are removed before the key is created
+DELETE FROM Bet duplicate
+USING Bet original
+WHERE duplicate.network_id = original.network_id
+    AND duplicate.transaction_hash = original.transaction_hash
+    AND duplicate.log_index = original.log_index
+    AND duplicate.id > original.id;
+
+DROP INDEX IF EXISTS bet_unique_idx;
+CREATE UNIQUE INDEX bet_unique_idx ON Bet(network_id, transaction_hash, log_index);
+CREATE INDEX IF NOT EXISTS bet_block_idx ON Bet(network_id, block_number);
+
+-- the games' events can be described with their declaration and Solidity JSON abi
+ALTER TABLE GameAbi ADD COLUMN IF NOT EXISTS event TEXT;
+ALTER TABLE GameAbi ADD COLUMN IF NOT EXISTS abi TEXT;
+
+-- units of the games' `multiplier` event field per 1.0x, 10000 for basis points
+ALTER TABLE Game ADD COLUMN IF NOT EXISTS multiplier_precision BIGINT NOT NULL DEFAULT 10000
+    CHECK (multiplier_precision > 0);
+CREATE OR REPLACE VIEW GameInfo AS
+    SELECT Game.id as id,
+            Game.network_id as network_id,
+            Game.name as name,
+            Game.address as address,
+            GameAbi.signature as event_signature,
+            GameAbi.types as event_types,
+            GameAbi.names as event_names,
+            GameAbi.event as event,
+            GameAbi.abi as event_abi,
+            Game.multiplier_precision as multiplier_precision
+        FROM Game
+    INNER JOIN GameAbi
+        ON Game.result_event_signature = GameAbi.signature;
+
+-- one whole token in its smallest units has to fit into the price computations
+ALTER TABLE Token ADD COLUMN IF NOT EXISTS decimals BIGINT NOT NULL DEFAULT 18;
+ALTER TABLE Token DROP CONSTRAINT IF EXISTS token_decimals_check;
+ALTER TABLE Token ADD CONSTRAINT token_decimals_check CHECK (decimals BETWEEN 0 AND 28);
+
+-- blocks the bets of the network wait for before they're stored
+ALTER TABLE Network ADD COLUMN IF NOT EXISTS confirmations BIGINT NOT NULL DEFAULT 0;
+CREATE OR REPLACE VIEW NetworkInfo AS
+    SELECT network.id as network_id,
+            network.name as network_name,
+            network.short_name as short_name,
+            nativecurrency.name as currency_name,
+            nativecurrency.symbol as currency_symbol,
+            nativecurrency.decimals,
+            network.confirmations as confirmations
+        FROM Network
+    INNER JOIN NativeCurrency
+        ON Network.native_currency_id = NativeCurrency.id;
+
+-- the price reports its staleness
+ALTER TABLE TokenPrice ADD COLUMN IF NOT EXISTS updated_at TIMESTAMP NOT NULL DEFAULT now();
+
+-- the network's sync status reports when its cursor last moved
+ALTER TABLE LastBlock ADD COLUMN IF NOT EXISTS updated_at TIMESTAMP NOT NULL DEFAULT now();
+
+-- argon2id PHC strings replace the blake2b digests, bumping token_version revokes
+-- the issued access tokens
+ALTER TABLE Partner ALTER COLUMN password TYPE TEXT;
+ALTER TABLE Partner ADD COLUMN IF NOT EXISTS token_version BIGINT NOT NULL DEFAULT 0;
+
+-- the tables added since the first deployment
+-- every polled price of the token,
+-- samples deviating too much from the previous one are flagged as anomalous
+CREATE TABLE IF NOT EXISTS TokenPriceHistory(
+    id BIGSERIAL PRIMARY KEY,
+    token_name TEXT NOT NULL,
+    price DOUBLE PRECISION NOT NULL,
+    anomalous BOOLEAN NOT NULL DEFAULT FALSE,
+    timestamp TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS token_price_history_idx ON TokenPriceHistory(token_name, timestamp);
+
+-- the historical price of the token closest to the moment
+CREATE OR REPLACE FUNCTION token_price_at(token TEXT, at TIMESTAMP)
+RETURNS DOUBLE PRECISION AS $$
+    SELECT price FROM (
+        (SELECT price, at - timestamp AS distance
+            FROM TokenPriceHistory
+            WHERE token_name = token AND NOT anomalous AND timestamp <= at
+            ORDER BY timestamp DESC
+            LIMIT 1)
+        UNION ALL
+        (SELECT price, timestamp - at AS distance
+            FROM TokenPriceHistory
+            WHERE token_name = token AND NOT anomalous AND timestamp > at
+            ORDER BY timestamp ASC
+            LIMIT 1)
+    ) AS candidates
+    ORDER BY distance
+    LIMIT 1
+$$ LANGUAGE SQL STABLE;
+
+-- the price if it was updated recently enough, NULL if it's stale
+CREATE OR REPLACE FUNCTION token_price_fresh(price DOUBLE PRECISION, updated_at TIMESTAMP)
+RETURNS DOUBLE PRECISION AS $$
+    SELECT CASE
+        WHEN updated_at >= now() - interval '15 minutes' THEN price
+        ELSE NULL
+    END
+$$ LANGUAGE SQL STABLE;
+
+-- game specific outcome of the bet: cards, dice rolls, coin sides...
+CREATE TABLE IF NOT EXISTS BetResult(
+    bet_id BIGINT PRIMARY KEY,
+    result JSONB NOT NULL,
+
+    CONSTRAINT fk_bet
+        FOREIGN KEY(bet_id)
+            REFERENCES Bet(id)
+            ON DELETE CASCADE
+);
+
+-- price sources of the network's tokens, token_address limits the source to a single token
+-- kind 'univ2': getAmountsOut of router_address through the space separated path
+--     ending with a USD stablecoin with quote_decimals
+-- kind 'static': fixed price, for the stablecoins
+-- several sources of a token are aggregated with the median
+CREATE TABLE IF NOT EXISTS PriceSource(
+    id BIGSERIAL PRIMARY KEY,
+    network_id BIGINT NOT NULL,
+    token_address character(42),
+    kind TEXT NOT NULL,
+    router_address character(42),
+    path TEXT,
+    quote_decimals BIGINT,
+    price DOUBLE PRECISION,
+
+    CONSTRAINT univ2_config CHECK (kind <> 'univ2' OR (
+        router_address IS NOT NULL
+        AND path IS NOT NULL
+        AND quote_decimals BETWEEN 0 AND 28
+    )),
+    CONSTRAINT static_config CHECK (kind <> 'static' OR price IS NOT NULL),
+    CONSTRAINT known_kind CHECK (kind IN ('univ2', 'static')),
+
+    CONSTRAINT fk_network
+        FOREIGN KEY(network_id)
+            REFERENCES Network(id)
+            ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS price_source_network_idx ON PriceSource(network_id);
+
+-- bets the db listener failed to store after the retries
+CREATE TABLE IF NOT EXISTS BetDeadLetter(
+    id BIGSERIAL PRIMARY KEY,
+    network_id BIGINT NOT NULL,
+    transaction_hash character(66) NOT NULL,
+    log_index BIGINT NOT NULL,
+    bet JSONB NOT NULL,
+    error TEXT NOT NULL,
+    timestamp TIMESTAMP NOT NULL DEFAULT now()
+);
+
+-- game logs which couldn't be turned into bets, kept raw to be processed again
+-- once the game's abi is fixed
+CREATE TABLE IF NOT EXISTS LogDeadLetter(
+    id BIGSERIAL PRIMARY KEY,
+    network_id BIGINT NOT NULL,
+    block_number BIGINT NOT NULL,
+    block_hash character(66) NOT NULL,
+    transaction_hash character(66) NOT NULL,
+    log_index BIGINT NOT NULL,
+    address character(42) NOT NULL,
+    topics TEXT[] NOT NULL,
+    data TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    -- time the log's block was mined, the bet is valued at it once recovered
+    block_timestamp TIMESTAMP,
+    timestamp TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS logdeadletter_unique_idx ON LogDeadLetter(network_id, transaction_hash, log_index);
+
+-- historical block ranges to ingest, insert a row to backfill on demand
+-- game_id limits the backfill to a single game
+CREATE TABLE IF NOT EXISTS BackfillJob(
+    id BIGSERIAL PRIMARY KEY,
+    network_id BIGINT NOT NULL,
+    game_id BIGINT,
+    from_block BIGINT NOT NULL,
+    to_block BIGINT NOT NULL,
+    processed_block BIGINT,
+    done BOOLEAN NOT NULL DEFAULT FALSE,
+
+    CONSTRAINT fk_network
+        FOREIGN KEY(network_id)
+            REFERENCES Network(id)
+            ON DELETE CASCADE,
+    CONSTRAINT fk_game
+        FOREIGN KEY(game_id)
+            REFERENCES Game(id)
+            ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS backfilljob_pending_idx ON BackfillJob(network_id) WHERE NOT done;
+
+-- only the blake2b hashes of the refresh tokens are stored, every refresh marks
+-- the token as used and issues the next one of the same family
+CREATE TABLE IF NOT EXISTS RefreshToken(
+    id BIGSERIAL PRIMARY KEY,
+    token_hash char(128) NOT NULL UNIQUE,
+    family char(64) NOT NULL,
+    partner_id TEXT NOT NULL REFERENCES Partner(main_wallet) ON DELETE CASCADE,
+    expires_at TIMESTAMP NOT NULL,
+    used BOOLEAN NOT NULL DEFAULT FALSE,
+    revoked BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL DEFAULT Now()
+);
+
+CREATE INDEX IF NOT EXISTS refreshtoken_family_idx ON RefreshToken(family);
+CREATE INDEX IF NOT EXISTS refreshtoken_partner_idx ON RefreshToken(partner_id);
+
+-- nonces handed out for sign-in with ethereum, deleted once used
+CREATE TABLE IF NOT EXISTS SiweNonce(
+    nonce TEXT PRIMARY KEY,
+    expires_at TIMESTAMP NOT NULL
+);
+
+-- nonces of the signed wallet requests, kept until their signatures get too old anyway
+CREATE TABLE IF NOT EXISTS SignatureNonce(
+    wallet TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+
+    PRIMARY KEY(wallet, nonce)
+);
+
+CREATE INDEX IF NOT EXISTS signaturenonce_expires_idx ON SignatureNonce(expires_at);
+
+-- the views over the upgraded tables
+DROP VIEW IF EXISTS BetInfo;
+CREATE VIEW BetInfo AS
+    SELECT Bet.id as id,
+            Bet.transaction_hash as transaction_hash,
+            Bet.player as player,
+            Nickname.nickname as player_nickname,
+            Bet.timestamp as timestamp,
+            Bet.game_id as game_id,
+            Game.name as game_name,
+            Bet.wager as wager,
+            Bet.token_address as token_address,
+            Token.name as token_name,
+            Bet.network_id as network_id,
+            Network.name as network_name,
+            Bet.bets as bets,
+            Bet.multiplier as multiplier,
+            Bet.profit as profit,
+            BetResult.result as game_result
+        FROM Bet
+    INNER JOIN Game
+        ON Bet.game_id = Game.id
+	INNER JOIN Network
+        ON Bet.network_id = Network.id
+	INNER JOIN Token
+        ON Bet.token_address = Token.contract_address
+	LEFT JOIN Nickname
+        ON Bet.player = Nickname.address
+	LEFT JOIN BetResult
+        ON Bet.id = BetResult.bet_id;
+
+DROP VIEW IF EXISTS Totals;
+CREATE VIEW Totals AS
+    SELECT 
+        COUNT(bet.id) AS bets_amount,
+        COUNT(DISTINCT bet.player) AS player_amount,
+        (SELECT 
+            SUM((bet.wager/power(10::numeric, token.decimals))*bet.bets*price.price)
+                from bet
+                INNER JOIN token
+                    ON bet.token_address = token.contract_address
+                CROSS JOIN LATERAL (SELECT token_price_at(token.name, bet.timestamp) AS price) AS price)
+    FROM bet;
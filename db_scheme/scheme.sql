@@ -9,6 +9,14 @@ DROP TABLE IF EXISTS Player CASCADE;
 DROP TABLE IF EXISTS Bet CASCADE;
 DROP TABLE IF EXISTS BanWords CASCADE;
 DROP TABLE IF EXISTS PancakeAddress CASCADE;
+DROP TABLE IF EXISTS PriceSource CASCADE;
+DROP TABLE IF EXISTS BetResult CASCADE;
+DROP TABLE IF EXISTS BackfillJob CASCADE;
+DROP TABLE IF EXISTS TokenPriceHistory CASCADE;
+DROP TABLE IF EXISTS BetDeadLetter CASCADE;
+DROP TABLE IF EXISTS LogDeadLetter CASCADE;
+DROP FUNCTION IF EXISTS token_price_at;
+DROP FUNCTION IF EXISTS token_price_fresh;
 DROP VIEW IF EXISTS BetInfo;
 DROP VIEW IF EXISTS VIEW;
 
@@ -24,6 +32,7 @@ CREATE TABLE IF NOT EXISTS Network(
     name TEXT NOT NULL,
     short_name TEXT NOT NULL,
     native_currency_id BIGSERIAL NOT NULL,
+    confirmations BIGINT NOT NULL DEFAULT 0,
 
     CONSTRAINT fk_nativecurrency
         FOREIGN KEY(native_currency_id)
@@ -36,7 +45,8 @@ CREATE VIEW NetworkInfo AS
             network.short_name as short_name,
             nativecurrency.name as currency_name,
             nativecurrency.symbol as currency_symbol,
-            nativecurrency.decimals
+            nativecurrency.decimals,
+            network.confirmations as confirmations
         FROM Network 
     INNER JOIN NativeCurrency 
         ON Network.native_currency_id = NativeCurrency.id;
@@ -66,6 +76,8 @@ CREATE TABLE IF NOT EXISTS Token(
     network_id BIGSERIAL NOT NULL,
     name TEXT NOT NULL,
     contract_address character(42) NOT NULL,
+    -- one whole token in its smallest units has to fit into the price computations
+    decimals BIGINT NOT NULL DEFAULT 18 CHECK (decimals BETWEEN 0 AND 28),
 
     CONSTRAINT fk_network
         FOREIGN KEY(network_id)
@@ -78,16 +90,67 @@ CREATE INDEX token_idx ON Token(contract_address);
 CREATE TABLE IF NOT EXISTS TokenPrice(
     id BIGSERIAL PRIMARY KEY,
     token_name TEXT NOT NULL,
-    price DOUBLE PRECISION
+    price DOUBLE PRECISION,
+    updated_at TIMESTAMP NOT NULL DEFAULT now()
 );
+ALTER TABLE TokenPrice ADD COLUMN IF NOT EXISTS updated_at TIMESTAMP NOT NULL DEFAULT now();
 
 CREATE UNIQUE INDEX token_price_idx ON TokenPrice(token_name);
 
+-- every polled price of the token,
+-- samples deviating too much from the previous one are flagged as anomalous
+CREATE TABLE IF NOT EXISTS TokenPriceHistory(
+    id BIGSERIAL PRIMARY KEY,
+    token_name TEXT NOT NULL,
+    price DOUBLE PRECISION NOT NULL,
+    anomalous BOOLEAN NOT NULL DEFAULT FALSE,
+    timestamp TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX token_price_history_idx ON TokenPriceHistory(token_name, timestamp);
+
+-- the historical price of the token closest to the moment
+CREATE OR REPLACE FUNCTION token_price_at(token TEXT, at TIMESTAMP)
+RETURNS DOUBLE PRECISION AS $$
+    SELECT price FROM (
+        (SELECT price, at - timestamp AS distance
+            FROM TokenPriceHistory
+            WHERE token_name = token AND NOT anomalous AND timestamp <= at
+            ORDER BY timestamp DESC
+            LIMIT 1)
+        UNION ALL
+        (SELECT price, timestamp - at AS distance
+            FROM TokenPriceHistory
+            WHERE token_name = token AND NOT anomalous AND timestamp > at
+            ORDER BY timestamp ASC
+            LIMIT 1)
+    ) AS candidates
+    ORDER BY distance
+    LIMIT 1
+$$ LANGUAGE SQL STABLE;
+
+-- the price if it was updated recently enough, NULL if it's stale
+CREATE OR REPLACE FUNCTION token_price_fresh(price DOUBLE PRECISION, updated_at TIMESTAMP)
+RETURNS DOUBLE PRECISION AS $$
+    SELECT CASE
+        WHEN updated_at >= now() - interval '15 minutes' THEN price
+        ELSE NULL
+    END
+$$ LANGUAGE SQL STABLE;
+
+-- abi is the Solidity JSON abi of the event, it replaces the legacy types and names
+-- and has to name the player's address parameter `player`,
+-- event is the declaration like `Name(address,uint256)`,
+-- when set it's checked against the signature and the types
 CREATE TABLE IF NOT EXISTS GameAbi(
     signature character(66) NOT NULL PRIMARY KEY,
     types TEXT NOT NULL,
-    names TEXT NOT NULL
+    names TEXT NOT NULL,
+    event TEXT,
+    abi TEXT
 );
+ALTER TABLE GameAbi ADD COLUMN IF NOT EXISTS event TEXT;
+ALTER TABLE GameAbi ADD COLUMN IF NOT EXISTS abi TEXT;
 
 -- CREATE UNIQUE INDEX game_address_idx ON GameAbi(signature);
 
@@ -97,6 +160,8 @@ CREATE TABLE IF NOT EXISTS Game(
     name TEXT NOT NULL,
     address character(42) NOT NULL,
     result_event_signature character(66) NOT NULL,
+    -- units of the event's `multiplier` field per 1.0x, 10000 for basis points
+    multiplier_precision BIGINT NOT NULL DEFAULT 10000 CHECK (multiplier_precision > 0),
 
     CONSTRAINT fk_network
         FOREIGN KEY(network_id)
@@ -118,7 +183,10 @@ CREATE VIEW GameInfo AS
             Game.address as address,
             GameAbi.signature as event_signature,
             GameAbi.types as event_types,
-            GameAbi.names as event_names
+            GameAbi.names as event_names,
+            GameAbi.event as event,
+            GameAbi.abi as event_abi,
+            Game.multiplier_precision as multiplier_precision
         FROM Game
     INNER JOIN GameAbi 
         ON Game.result_event_signature = GameAbi.signature;
@@ -164,6 +232,9 @@ CREATE TABLE IF NOT EXISTS Bet(
     bets BIGINT NOT NULL,
     multiplier DOUBLE PRECISION NOT NULL,
     profit DECIMAL(1000, 0) NOT NULL,
+    block_number BIGINT NOT NULL,
+    block_hash character(66) NOT NULL,
+    log_index BIGINT NOT NULL DEFAULT 0,
 
     CONSTRAINT fk_game
         FOREIGN KEY(game_id)
@@ -174,11 +245,23 @@ CREATE TABLE IF NOT EXISTS Bet(
             REFERENCES Network(id)
 );
 
-CREATE UNIQUE INDEX bet_unique_idx ON Bet(transaction_hash, network_id);
+CREATE UNIQUE INDEX bet_unique_idx ON Bet(network_id, transaction_hash, log_index);
 CREATE INDEX bet_player_idx ON Bet(player);
 CREATE INDEX bet_game_idx ON Bet(game_id);
 CREATE INDEX bet_idx ON Bet(player, game_id);
 CREATE INDEX last_bets_idx ON Bet(timestamp desc);
+CREATE INDEX bet_block_idx ON Bet(network_id, block_number);
+
+-- game specific outcome of the bet: cards, dice rolls, coin sides...
+CREATE TABLE IF NOT EXISTS BetResult(
+    bet_id BIGINT PRIMARY KEY,
+    result JSONB NOT NULL,
+
+    CONSTRAINT fk_bet
+        FOREIGN KEY(bet_id)
+            REFERENCES Bet(id)
+            ON DELETE CASCADE
+);
 
 
 CREATE TABLE IF NOT EXISTS PancakeAddress(
@@ -195,6 +278,37 @@ CREATE TABLE IF NOT EXISTS PancakeAddress(
 
 CREATE UNIQUE INDEX pancake_idx ON PancakeAddress(network_id);
 
+-- price sources of the network's tokens, token_address limits the source to a single token
+-- kind 'univ2': getAmountsOut of router_address through the space separated path
+--     ending with a USD stablecoin with quote_decimals
+-- kind 'static': fixed price, for the stablecoins
+-- several sources of a token are aggregated with the median
+CREATE TABLE IF NOT EXISTS PriceSource(
+    id BIGSERIAL PRIMARY KEY,
+    network_id BIGINT NOT NULL,
+    token_address character(42),
+    kind TEXT NOT NULL,
+    router_address character(42),
+    path TEXT,
+    quote_decimals BIGINT,
+    price DOUBLE PRECISION,
+
+    CONSTRAINT univ2_config CHECK (kind <> 'univ2' OR (
+        router_address IS NOT NULL
+        AND path IS NOT NULL
+        AND quote_decimals BETWEEN 0 AND 28
+    )),
+    CONSTRAINT static_config CHECK (kind <> 'static' OR price IS NOT NULL),
+    CONSTRAINT known_kind CHECK (kind IN ('univ2', 'static')),
+
+    CONSTRAINT fk_network
+        FOREIGN KEY(network_id)
+            REFERENCES Network(id)
+            ON DELETE CASCADE
+);
+
+CREATE INDEX price_source_network_idx ON PriceSource(network_id);
+
 CREATE VIEW BetInfo AS 
     SELECT Bet.id as id,
             Bet.transaction_hash as transaction_hash,
@@ -210,7 +324,8 @@ CREATE VIEW BetInfo AS
             Network.name as network_name,
             Bet.bets as bets,
             Bet.multiplier as multiplier,
-            Bet.profit as profit
+            Bet.profit as profit,
+            BetResult.result as game_result
         FROM Bet
     INNER JOIN Game
         ON Bet.game_id = Game.id
@@ -219,22 +334,20 @@ CREATE VIEW BetInfo AS
 	INNER JOIN Token
         ON Bet.token_address = Token.contract_address
 	LEFT JOIN Nickname
-        ON Bet.player = Nickname.address;
+        ON Bet.player = Nickname.address
+	LEFT JOIN BetResult
+        ON Bet.id = BetResult.bet_id;
 
 CREATE VIEW Totals AS
     SELECT 
         COUNT(bet.id) AS bets_amount,
         COUNT(DISTINCT bet.player) AS player_amount,
         (SELECT 
-            SUM((bet.wager/1000000000000000000)*bet.bets*price.price)
+            SUM((bet.wager/power(10::numeric, token.decimals))*bet.bets*price.price)
                 from bet
-                INNER JOIN (SELECT 
-                    token.name AS name,
-                    token.contract_address AS address,
-                    tokenprice.price AS price
-    FROM token
-    INNER JOIN tokenprice ON token.name=tokenprice.token_name) AS price
-            ON bet.token_address = price.address)
+                INNER JOIN token
+                    ON bet.token_address = token.contract_address
+                CROSS JOIN LATERAL (SELECT token_price_at(token.name, bet.timestamp) AS price) AS price)
     FROM bet;
 
 CREATE TABLE IF NOT EXISTS BanWords(
@@ -242,17 +355,74 @@ CREATE TABLE IF NOT EXISTS BanWords(
     word TEXT
 );
 
+-- id is the next block the network handler scans
 CREATE TABLE IF NOT EXISTS LastBlock(
     id BIGINT NOT NULL,
     network_id BIGINT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT now(),
 
     CONSTRAINT fk_network
         FOREIGN KEY(network_id)
             REFERENCES Network(id)
 );
+ALTER TABLE LastBlock ADD COLUMN IF NOT EXISTS updated_at TIMESTAMP NOT NULL DEFAULT now();
 
 CREATE UNIQUE INDEX lastblock_unique_idx ON LastBlock(network_id);
 
+-- bets the db listener failed to store after the retries
+CREATE TABLE IF NOT EXISTS BetDeadLetter(
+    id BIGSERIAL PRIMARY KEY,
+    network_id BIGINT NOT NULL,
+    transaction_hash character(66) NOT NULL,
+    log_index BIGINT NOT NULL,
+    bet JSONB NOT NULL,
+    error TEXT NOT NULL,
+    timestamp TIMESTAMP NOT NULL DEFAULT now()
+);
+
+-- game logs which couldn't be turned into bets, kept raw to be processed again
+-- once the game's abi is fixed
+CREATE TABLE IF NOT EXISTS LogDeadLetter(
+    id BIGSERIAL PRIMARY KEY,
+    network_id BIGINT NOT NULL,
+    block_number BIGINT NOT NULL,
+    block_hash character(66) NOT NULL,
+    transaction_hash character(66) NOT NULL,
+    log_index BIGINT NOT NULL,
+    address character(42) NOT NULL,
+    topics TEXT[] NOT NULL,
+    data TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    -- time the log's block was mined, the bet is valued at it once recovered
+    block_timestamp TIMESTAMP,
+    timestamp TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE UNIQUE INDEX logdeadletter_unique_idx ON LogDeadLetter(network_id, transaction_hash, log_index);
+
+-- historical block ranges to ingest, insert a row to backfill on demand
+-- game_id limits the backfill to a single game
+CREATE TABLE IF NOT EXISTS BackfillJob(
+    id BIGSERIAL PRIMARY KEY,
+    network_id BIGINT NOT NULL,
+    game_id BIGINT,
+    from_block BIGINT NOT NULL,
+    to_block BIGINT NOT NULL,
+    processed_block BIGINT,
+    done BOOLEAN NOT NULL DEFAULT FALSE,
+
+    CONSTRAINT fk_network
+        FOREIGN KEY(network_id)
+            REFERENCES Network(id)
+            ON DELETE CASCADE,
+    CONSTRAINT fk_game
+        FOREIGN KEY(game_id)
+            REFERENCES Game(id)
+            ON DELETE CASCADE
+);
+
+CREATE INDEX backfilljob_pending_idx ON BackfillJob(network_id) WHERE NOT done;
+
 
 -- PARTNERS
 
@@ -289,10 +459,46 @@ CREATE TABLE IF NOT EXISTS Partner(
     program PartnerProgram NOT NULL,
     is_verified boolean NOT NULL,
     login varchar(25) UNIQUE,
-    password char(128) NOT NULL,
+    -- argon2id PHC string, or the blake2b hex digest until the next login
+    password TEXT NOT NULL,
     registration_time TIMESTAMP DEFAULT Now(),
-    language TEXT
+    language TEXT,
+    -- part of the access tokens, bumped to revoke the issued ones
+    token_version BIGINT NOT NULL DEFAULT 0
+);
+ALTER TABLE Partner ADD COLUMN IF NOT EXISTS token_version BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE Partner ALTER COLUMN password TYPE TEXT;
+
+-- only the blake2b hashes of the refresh tokens are stored, every refresh marks
+-- the token as used and issues the next one of the same family
+CREATE TABLE IF NOT EXISTS RefreshToken(
+    id BIGSERIAL PRIMARY KEY,
+    token_hash char(128) NOT NULL UNIQUE,
+    family char(64) NOT NULL,
+    partner_id TEXT NOT NULL REFERENCES Partner(main_wallet) ON DELETE CASCADE,
+    expires_at TIMESTAMP NOT NULL,
+    used BOOLEAN NOT NULL DEFAULT FALSE,
+    revoked BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL DEFAULT Now()
 );
+CREATE INDEX IF NOT EXISTS refreshtoken_family_idx ON RefreshToken(family);
+CREATE INDEX IF NOT EXISTS refreshtoken_partner_idx ON RefreshToken(partner_id);
+
+-- nonces handed out for sign-in with ethereum, deleted once used
+CREATE TABLE IF NOT EXISTS SiweNonce(
+    nonce TEXT PRIMARY KEY,
+    expires_at TIMESTAMP NOT NULL
+);
+
+-- nonces of the signed wallet requests, kept until their signatures get too old anyway
+CREATE TABLE IF NOT EXISTS SignatureNonce(
+    wallet TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+
+    PRIMARY KEY(wallet, nonce)
+);
+CREATE INDEX IF NOT EXISTS signaturenonce_expires_idx ON SignatureNonce(expires_at);
 
 CREATE TABLE IF NOT EXISTS PartnerContact(
     id BIGSERIAL PRIMARY KEY,
@@ -438,6 +644,17 @@ INSERT INTO public.token(
 -- 	address, usdt_address, network_id)
 -- 	VALUES ('0x10ED43C718714eb63d5aA57B78B54704E256024E', '0x55d398326f99059fF775485246999027B3197955', '97');
 
+-- pricesource
+INSERT INTO public.pricesource(
+	network_id, kind, router_address, path, quote_decimals)
+	VALUES (
+        56,
+        'univ2',
+        '0x10ED43C718714eb63d5aA57B78B54704E256024E',
+        '0xbb4CdB9CBd36B01bD1cBaEBF2De08d9173bc095c 0x55d398326f99059fF775485246999027B3197955',
+        18
+    );
+
 -- gameabi
 INSERT INTO public.gameabi(
 	signature, types, names)
@@ -22,12 +22,19 @@ use warp::{
 #[openapi(
         paths(
             handlers::get_networks,
+            handlers::get_network_sync,
             handlers::get_rpcs,
+            handlers::get_rpcs_health,
             handlers::get_block_explorers,
             handlers::get_tokens,
+            handlers::get_token_price,
+            handlers::get_token_price_history,
             handlers::get_game,
             handlers::get_nickname,
             handlers::set_nickname,
+            handlers::set_session_nickname,
+            handlers::get_siwe_nonce,
+            handlers::login_player,
             handlers::get_player,
             handlers::get_player_bets,
             handlers::get_player_bets_inc,
@@ -35,6 +42,7 @@ use warp::{
             handlers::get_bets_for_game,
             handlers::get_latest_games,
             handlers::get_totals,
+            handlers::get_db_queue,
             handlers::register_partner,
             handlers::get_partner,
             handlers::connect_wallet,
@@ -55,6 +63,8 @@ use warp::{
             handlers::get_partner_connected_wallets_with_deposits_amount,
             handlers::get_partner_connected_wallets_info,
             handlers::login_partner,
+            handlers::login_partner_wallet,
+            handlers::refresh_partner_token,
             handlers::get_partner_clicks_exact_date,
             handlers::get_connected_totals,
             handlers::submit_withdrawal,
@@ -62,6 +72,27 @@ use warp::{
             handlers::submit_question,
             handlers::get_withdrawal_requests,
             handlers::get_partner_connected_wallets_betted_exact_date,
+            handlers::add_network,
+            handlers::update_network,
+            handlers::delete_network,
+            handlers::add_rpc,
+            handlers::update_rpc,
+            handlers::delete_rpc,
+            handlers::add_block_explorer,
+            handlers::update_block_explorer,
+            handlers::delete_block_explorer,
+            handlers::add_token,
+            handlers::update_token,
+            handlers::delete_token,
+            handlers::add_game,
+            handlers::update_game,
+            handlers::delete_game,
+            handlers::add_game_abi,
+            handlers::update_game_abi,
+            handlers::delete_game_abi,
+            handlers::get_dead_letters,
+            handlers::reprocess_dead_letters,
+            handlers::recompute_multipliers,
         ),
         components(schemas(
             json_requests::SetNickname,
@@ -73,30 +104,52 @@ use warp::{
             json_requests::AddPartnerSubid,
             json_requests::ConnectWallet,
             json_requests::Login,
+            json_requests::RefreshTokenRequest,
+            json_requests::SiweLogin,
+            json_requests::SessionNickname,
             json_requests::WithdrawRequest,
             json_requests::ChangePasswordRequest,
             json_requests::SubmitQuestion,
+            json_requests::NetworkUrl,
+            json_requests::NewToken,
+            json_requests::NewGame,
 
             json_responses::JsonResponse,
             json_responses::ResponseBody,
             json_responses::ErrorText,
             json_responses::InfoText,
+            json_responses::CreatedId,
+            json_responses::LogDeadLetters,
+            json_responses::DeadLettersReprocessed,
+            json_responses::MultipliersRecomputed,
+            db_models::LogDeadLetter,
             json_responses::Rpcs,
+            json_responses::TokenPrice,
+            json_responses::TokenPriceHistory,
+            db_models::TokenPricePoint,
+            json_responses::RpcsHealth,
+            json_responses::RpcHealth,
             json_responses::BlockExplorers,
             json_responses::Tokens,
             json_responses::Bets,
             json_responses::NetworkFullInfo,
             json_responses::Networks,
+            json_responses::NetworkSync,
+            json_responses::DbQueue,
             json_responses::LatestGames,
             json_responses::PartnerInfo,
             json_responses::PartnerSiteInfo,
             json_responses::ConnectedWalletsTimeMapped,
             json_responses::ClicksTimeMapped,
             json_responses::AccessToken,
+            json_responses::SiweNonce,
+            json_responses::PlayerSession,
             json_responses::ConnectedWalletInfo,
 
             db_models::Totals,
             db_models::BetInfo,
+            json_responses::GameResult,
+            json_responses::Card,
             db_models::BlockExplorerUrl,
             db_models::Game,
             db_models::GameAbi,
@@ -1,18 +1,54 @@
 pub use tokio::sync::broadcast::{channel, Receiver, Sender};
 
-use crate::models::db_models::{Bet, TokenPrice};
-use crate::models::json_responses::BetInfoResponse;
+use crate::models::db_models::{Bet, LogDeadLetter, TokenPrice};
+use crate::models::json_responses::{BetInfoResponse, WsDataFeedMessage};
 
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc::{
+    self,
+    error::{SendError, TrySendError},
+};
 pub use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+pub use tokio::sync::watch;
+use tracing::warn;
 
 pub struct DbPropagatedBet {
     pub bet: Bet,
     pub block_id: u64,
 }
 
+/// Bets of the network starting from `from_block` were orphaned
+pub struct DbReorg {
+    pub network_id: i64,
+    pub from_block: u64,
+}
+
+/// Bets found during a poll of the network, stored along with the block
+/// the network handler resumes from after a restart
+pub struct DbPoll {
+    pub network_id: i64,
+    pub bets: Vec<Bet>,
+    /// Logs which couldn't be turned into bets
+    pub dead_letters: Vec<LogDeadLetter>,
+    pub last_block: u64,
+}
+
 pub enum DbMessage {
-    PlaceBet(DbPropagatedBet),
+    Poll(DbPoll),
     NewPrice(TokenPrice),
+    Reorg(DbReorg),
+}
+
+/// Change of the networks configuration made through the admin api
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegistryChange {
+    /// The network itself, its rpcs, tokens or games were changed,
+    /// its handlers are restarted with the new configuration
+    Network(i64),
+    /// The games of the network were changed, they're reloaded
+    /// by its running handlers keeping the block cursor
+    Games(i64),
 }
 
 #[derive(Debug, Clone)]
@@ -20,13 +56,75 @@ pub struct PropagatedBet {
     pub bet: BetInfoResponse,
     pub game_name: String,
     pub network_name: String,
+    /// The bet was dropped by a chain reorganization before being confirmed
+    pub reverted: bool,
+}
+
+/// State of the queue between the handlers and the db listener
+#[derive(Default, Debug)]
+pub struct DbQueueMetrics {
+    /// Messages waiting to be written
+    pub depth: AtomicUsize,
+    /// Sends that had to wait for the db listener to free some room
+    pub stalls: AtomicU64,
+    /// Failed writes that were attempted again
+    pub retries: AtomicU64,
+    /// Bets moved to the dead letters after running out of retries
+    pub dead_letters: AtomicU64,
 }
 
-pub type DbReceiver = UnboundedReceiver<DbMessage>;
-pub type DbSender = UnboundedSender<DbMessage>;
+pub type SharedDbQueueMetrics = Arc<DbQueueMetrics>;
+
+/// Bounded sender to the db listener, the handlers wait while its queue is full
+#[derive(Clone)]
+pub struct DbSender {
+    sender: mpsc::Sender<DbMessage>,
+    metrics: SharedDbQueueMetrics,
+}
+
+impl DbSender {
+    pub async fn send(&self, msg: DbMessage) -> Result<(), SendError<DbMessage>> {
+        match self.sender.try_send(msg) {
+            Ok(()) => {}
+            Err(TrySendError::Full(msg)) => {
+                self.metrics.stalls.fetch_add(1, Ordering::Relaxed);
+                warn!("Db queue is full, waiting for the db listener");
+                self.sender.send(msg).await?;
+            }
+            Err(TrySendError::Closed(msg)) => return Err(SendError(msg)),
+        }
+        self.metrics.depth.store(
+            self.sender.max_capacity() - self.sender.capacity(),
+            Ordering::Relaxed,
+        );
+        Ok(())
+    }
+}
+
+pub type DbReceiver = mpsc::Receiver<DbMessage>;
+
+pub fn db_channel(capacity: usize, metrics: SharedDbQueueMetrics) -> (DbSender, DbReceiver) {
+    let (sender, receiver) = mpsc::channel(capacity);
+    (DbSender { sender, metrics }, receiver)
+}
+
+pub type RegistryReceiver = UnboundedReceiver<RegistryChange>;
+pub type RegistrySender = UnboundedSender<RegistryChange>;
 
 pub type BetReceiver = Receiver<PropagatedBet>;
 pub type BetSender = Sender<PropagatedBet>;
 
-pub type WsDataFeedReceiver = Receiver<BetInfoResponse>;
-pub type WsDataFeedSender = Sender<BetInfoResponse>;
+pub type WsDataFeedReceiver = Receiver<WsDataFeedMessage>;
+pub type WsDataFeedSender = Sender<WsDataFeedMessage>;
+
+/// Set to `true` once the process is requested to stop
+pub type ShutdownReceiver = watch::Receiver<bool>;
+pub type ShutdownSender = watch::Sender<bool>;
+
+/// Resolves once the shutdown is requested
+pub async fn shutdown_requested(shutdown: &mut ShutdownReceiver) {
+    if shutdown.wait_for(|stop| *stop).await.is_err() {
+        // the sender lives as long as the process does
+        std::future::pending::<()>().await
+    }
+}
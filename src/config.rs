@@ -1,4 +1,4 @@
-use std::{env, net::Ipv4Addr};
+use std::{collections::BTreeMap, env, net::Ipv4Addr, time::Duration};
 
 use lazy_static::lazy_static;
 use serde::Deserialize;
@@ -19,7 +19,47 @@ lazy_static! {
     // other params
     pub static ref PAGE_SIZE: i64 = env::var("PAGE_SIZE").unwrap().parse().unwrap();
     pub static ref ABIS_FOLDER: String = env::var("ABIS_FOLDER").unwrap();
-    pub static ref PASSWORD_SALT: String = env::var("PASSWORD_SALT").unwrap();
+    pub static ref ADMIN_TOKEN: String = env::var("ADMIN_TOKEN").unwrap();
+    pub static ref SHUTDOWN_TIMEOUT: Duration = Duration::from_millis(
+        env::var("SHUTDOWN_TIMEOUT_MS").unwrap().parse().unwrap()
+    );
+
+    // partner and player auth
+    pub static ref JWT_ISSUER: String = env::var("JWT_ISSUER").unwrap();
+    pub static ref JWT_PARTNER_AUDIENCE: String = env::var("JWT_PARTNER_AUDIENCE").unwrap();
+    pub static ref JWT_PLAYER_AUDIENCE: String = env::var("JWT_PLAYER_AUDIENCE").unwrap();
+    pub static ref JWT_KEY_ID: String = env::var("JWT_KEY_ID").unwrap();
+    pub static ref JWT_SECRETS: BTreeMap<String, String> = env::var("JWT_SECRETS")
+        .unwrap()
+        .split(',')
+        .map(|key| {
+            let (id, secret) = key.split_once(':').unwrap();
+            (id.trim().to_owned(), secret.trim().to_owned())
+        })
+        .collect();
+    pub static ref ACCESS_TOKEN_TTL: u64 = env::var("ACCESS_TOKEN_TTL_SECS").unwrap().parse().unwrap();
+    pub static ref REFRESH_TOKEN_TTL: u64 = env::var("REFRESH_TOKEN_TTL_SECS").unwrap().parse().unwrap();
+    pub static ref PLAYER_SESSION_TTL: u64 = env::var("PLAYER_SESSION_TTL_SECS").unwrap().parse().unwrap();
+    pub static ref SIWE_DOMAIN: String = env::var("SIWE_DOMAIN").unwrap();
+    pub static ref SIWE_ORIGIN: String = env::var("SIWE_ORIGIN").unwrap();
+    pub static ref SIWE_NONCE_TTL: u64 = env::var("SIWE_NONCE_TTL_SECS").unwrap().parse().unwrap();
+    pub static ref SIGNATURE_TTL: u64 = env::var("SIGNATURE_TTL_SECS").unwrap().parse().unwrap();
+
+    // log ingestion
+    pub static ref BACKFILL_WINDOW: u64 = env::var("BACKFILL_WINDOW").unwrap().parse().unwrap();
+    pub static ref BACKFILL_DELAY: Duration = Duration::from_millis(
+        env::var("BACKFILL_DELAY_MS").unwrap().parse().unwrap()
+    );
+
+    // db writer
+    pub static ref DB_QUEUE_SIZE: usize = env::var("DB_QUEUE_SIZE").unwrap().parse().unwrap();
+    pub static ref DB_RETRIES: u32 = env::var("DB_RETRIES").unwrap().parse().unwrap();
+    pub static ref DB_RETRY_DELAY: Duration = Duration::from_millis(
+        env::var("DB_RETRY_DELAY_MS").unwrap().parse().unwrap()
+    );
+
+    // token prices
+    pub static ref MAX_PRICE_DEVIATION: f64 = env::var("MAX_PRICE_DEVIATION").unwrap().parse().unwrap();
 }
 
 #[derive(Debug, Deserialize)]
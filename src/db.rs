@@ -1,16 +1,18 @@
 use crate::{
     config::DatabaseSettings,
     models::db_models::{
-        AmountConnectedWallets, Bet, BetInfo, BlockExplorerUrl, ConnectedWallet, Game, GameAbi,
-        GameInfo, LastBlock, LatestGames, Leaderboard, NetworkInfo, Nickname, Partner,
-        PartnerContact, PartnerProgram, PartnerSite, Player, PlayerTotals, PlayersTotals,
-        RefClicks, RpcUrl, SiteSubId, TimeBoundaries, Token, TokenPrice, Totals, Withdrawal,
+        AmountConnectedWallets, BackfillJob, Bet, BetBlock, BetInfo, BlockExplorerUrl,
+        ConnectedWallet, Game, GameAbi, GameInfo, LastBlock, LatestGames, Leaderboard,
+        LogDeadLetter, NetworkInfo, Nickname, Partner, PartnerContact, PartnerProgram, PartnerSite,
+        Player, PlayerTotals, PlayersTotals, PriceSourceConfig, RefClicks, RefreshToken, RpcUrl,
+        SiteSubId, TimeBoundaries, Token, TokenPricePoint, TokenPriceState, Totals, Withdrawal,
     },
-    models::json_requests::WithdrawRequest,
+    models::json_requests::{NewGame, NewToken, WithdrawRequest},
 };
 
 use chrono::{DateTime, Utc};
-use sqlx::{postgres::PgPoolOptions, PgPool};
+use sqlx::types::Json;
+use sqlx::{postgres::PgPoolOptions, PgConnection, PgPool};
 use tracing::info;
 
 #[derive(Debug, Clone)]
@@ -39,13 +41,33 @@ impl DB {
                 short_name AS "short_name!",
                 currency_name AS "currency_name!",
                 currency_symbol AS "currency_symbol!",
-                decimals as "decimals!"
+                decimals as "decimals!",
+                confirmations as "confirmations!"
             FROM NetworkInfo"#
         )
         .fetch_all(&self.db_pool)
         .await
     }
 
+    pub async fn query_network(&self, network_id: i64) -> Result<Option<NetworkInfo>, sqlx::Error> {
+        sqlx::query_as!(
+            NetworkInfo,
+            r#"SELECT 
+                network_id AS "network_id!",
+                network_name AS "network_name!",
+                short_name AS "short_name!",
+                currency_name AS "currency_name!",
+                currency_symbol AS "currency_symbol!",
+                decimals as "decimals!",
+                confirmations as "confirmations!"
+            FROM NetworkInfo
+            WHERE network_id = $1"#,
+            network_id
+        )
+        .fetch_optional(&self.db_pool)
+        .await
+    }
+
     pub async fn query_token(&self, address: &str) -> Result<Token, sqlx::Error> {
         sqlx::query_as_unchecked!(
             Token,
@@ -76,6 +98,23 @@ impl DB {
         .await
     }
 
+    pub async fn query_price_sources(
+        &self,
+        network_id: i64,
+    ) -> Result<Vec<PriceSourceConfig>, sqlx::Error> {
+        sqlx::query_as_unchecked!(
+            PriceSourceConfig,
+            r#"
+            SELECT *
+            FROM PriceSource
+            WHERE network_id = $1
+            "#,
+            network_id
+        )
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
     pub async fn get_unique_tokens(&self, network_id: i64) -> Result<Vec<Token>, sqlx::Error> {
         sqlx::query_as_unchecked!(
             Token,
@@ -83,7 +122,8 @@ impl DB {
             SELECT DISTINCT name,
                 contract_address,
                 id, 
-                network_id
+                network_id,
+                decimals
             FROM Token
             WHERE network_id = $1
             "#,
@@ -118,35 +158,105 @@ impl DB {
         .await
     }
 
+    /// Records the polled price, a price deviating from the previous sample
+    /// by more than `max_deviation` is flagged as anomalous and doesn't replace
+    /// the current price, returns whether the price was accepted
     pub async fn change_token_price(
         &self,
         token_name: &str,
         new_price: f64,
-    ) -> Result<(), sqlx::Error> {
+        max_deviation: f64,
+    ) -> Result<bool, sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let last_price = sqlx::query_scalar!(
+            "
+            SELECT price
+            FROM TokenPriceHistory
+            WHERE token_name = $1
+            ORDER BY timestamp DESC
+            LIMIT 1
+            ",
+            token_name,
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let anomalous = last_price
+            .is_some_and(|last| last > 0.0 && (new_price - last).abs() / last > max_deviation);
+
         sqlx::query!(
             "
-            INSERT INTO TokenPrice(token_name, price)
-            VALUES ($1, $2)
-            ON CONFLICT(token_name) DO UPDATE
-                SET price = excluded.price
+            INSERT INTO TokenPriceHistory(token_name, price, anomalous)
+            VALUES ($1, $2, $3)
             ",
             token_name,
             new_price,
+            anomalous,
         )
-        .execute(&self.db_pool)
+        .execute(&mut *tx)
         .await?;
 
-        Ok(())
+        if !anomalous {
+            sqlx::query!(
+                "
+                INSERT INTO TokenPrice(token_name, price, updated_at)
+                VALUES ($1, $2, now())
+                ON CONFLICT(token_name) DO UPDATE
+                    SET price = excluded.price,
+                        updated_at = excluded.updated_at
+                ",
+                token_name,
+                new_price,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(!anomalous)
+    }
+
+    pub async fn query_token_price_history(
+        &self,
+        token_name: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<TokenPricePoint>, sqlx::Error> {
+        sqlx::query_as_unchecked!(
+            TokenPricePoint,
+            r#"
+            SELECT price, anomalous, timestamp
+            FROM TokenPriceHistory
+            WHERE token_name = $1
+                AND timestamp >= $2
+                AND timestamp < $3
+            ORDER BY timestamp
+            LIMIT $4
+            "#,
+            token_name,
+            start.naive_utc(),
+            end.naive_utc(),
+            limit
+        )
+        .fetch_all(&self.db_pool)
+        .await
     }
 
     pub async fn query_token_price(
         &self,
         token_name: &str,
-    ) -> Result<Option<TokenPrice>, sqlx::Error> {
+    ) -> Result<Option<TokenPriceState>, sqlx::Error> {
         sqlx::query_as_unchecked!(
-            TokenPrice,
+            TokenPriceState,
             r#"
-            SELECT * FROM tokenprice WHERE token_name=$1 LIMIT 1
+            SELECT
+                price,
+                updated_at,
+                token_price_fresh(price, updated_at) IS NULL AS stale
+            FROM tokenprice
+            WHERE token_name=$1
+            LIMIT 1
             "#,
             token_name
         )
@@ -493,145 +603,667 @@ impl DB {
     }
 
     pub async fn place_bet(&self, bet: &Bet) -> Result<(), sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+        insert_bet(&mut tx, bet).await?;
+        tx.commit().await
+    }
+
+    /// Places the bets and the dead letters found during a poll of the network and moves
+    /// its cursor in one transaction, the cursor never gets ahead of the stored bets
+    pub async fn place_bets(
+        &self,
+        network_id: i64,
+        bets: &[Bet],
+        dead_letters: &[LogDeadLetter],
+        last_block: i64,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+        for bet in bets {
+            insert_bet(&mut tx, bet).await?;
+        }
+        for dead_letter in dead_letters {
+            insert_log_dead_letter(&mut tx, dead_letter).await?;
+        }
         sqlx::query!(
             "
-            INSERT INTO Bet(
-                transaction_hash,
-                player,
-                timestamp,
-                game_id,
-                wager,
-                token_address,
-                network_id,
-                bets,
-                multiplier,
-                profit
-            ) VALUES (
-                $1,
-                $2,
-                $3,
-                $4,
-                $5,
-                $6,
-                $7,
-                $8,
-                $9,
-                $10
-            )
+            INSERT INTO LastBlock(id, network_id, updated_at)
+            VALUES ($1, $2, now())
+            ON CONFLICT(network_id) DO UPDATE
+                SET id = excluded.id,
+                    updated_at = excluded.updated_at
+            ",
+            last_block,
+            network_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await
+    }
+
+    /// Keeps the bet which couldn't be stored along with the error
+    pub async fn add_bet_dead_letter(&self, bet: &Bet, error: &str) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "
+            INSERT INTO BetDeadLetter(network_id, transaction_hash, log_index, bet, error)
+            VALUES ($1, $2, $3, $4, $5)
+            ",
+            bet.network_id,
+            bet.transaction_hash,
+            bet.log_index,
+            Json(bet) as _,
+            error,
+        )
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn add_log_dead_letter(
+        &self,
+        dead_letter: &LogDeadLetter,
+    ) -> Result<(), sqlx::Error> {
+        insert_log_dead_letter(&mut *self.db_pool.acquire().await?, dead_letter).await
+    }
+
+    /// Dead letters of the network, newest first
+    pub async fn query_log_dead_letters(
+        &self,
+        network_id: i64,
+        last_id: Option<i64>,
+        page_size: i64,
+    ) -> Result<Vec<LogDeadLetter>, sqlx::Error> {
+        sqlx::query_as_unchecked!(
+            LogDeadLetter,
+            "
+            SELECT * FROM LogDeadLetter
+            WHERE network_id = $1 AND ($2::BIGINT IS NULL OR id < $2)
+            ORDER BY id DESC
+            LIMIT $3
+            ",
+            network_id,
+            last_id,
+            page_size
+        )
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    pub async fn query_all_log_dead_letters(
+        &self,
+        network_id: i64,
+    ) -> Result<Vec<LogDeadLetter>, sqlx::Error> {
+        sqlx::query_as_unchecked!(
+            LogDeadLetter,
+            "
+            SELECT * FROM LogDeadLetter
+            WHERE network_id = $1
+            ORDER BY block_number, log_index
+            ",
+            network_id
+        )
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    pub async fn delete_log_dead_letter(&self, id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "
+            DELETE FROM LogDeadLetter WHERE id = $1
+            ",
+            id
+        )
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn query_abi(&self, signature: &str) -> Result<GameAbi, sqlx::Error> {
+        sqlx::query_as_unchecked!(
+            GameAbi,
+            "
+            SELECT * FROM GameAbi WHERE signature=$1 LIMIT 1
+            ",
+            signature
+        )
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    pub async fn query_player_totals(&self, address: &str) -> Result<PlayerTotals, sqlx::Error> {
+        sqlx::query_as_unchecked!(
+            PlayerTotals,
+            r#"
+            SELECT 
+                    COUNT(bet.id) AS bets_amount,
+                    COUNT(case when bet.wager*bet.bets > bet.profit then 1 else null end) as lost_bets,
+					COUNT(case when bet.wager*bet.bets <= bet.profit then 1 else null end) as won_bets,
+                    SUM((bet.wager/power(10::numeric, token.decimals))*bet.bets*price.price) as total_wagered_sum,
+					SUM((bet.profit/power(10::numeric, token.decimals))*price.price) as gross_profit,
+					SUM((bet.profit/power(10::numeric, token.decimals))*price.price)-SUM((bet.wager/power(10::numeric, token.decimals))*bet.bets*price.price) as net_profit,
+					MAX((bet.profit/power(10::numeric, token.decimals))*price.price) as highest_win
+            FROM bet 
+			INNER JOIN token
+			    ON bet.token_address = token.contract_address AND bet.player = $1
+			CROSS JOIN LATERAL (SELECT token_price_at(token.name, bet.timestamp) AS price) AS price
+            "#,
+            address
+        )
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    pub async fn query_players_totals(
+        &self,
+        partner_wallet: &str,
+    ) -> Result<PlayersTotals, sqlx::Error> {
+        sqlx::query_as_unchecked!(
+            PlayersTotals,
+            r#"
+            SELECT 
+                    COUNT(bet.id) AS bets_amount,
+                    COUNT(case when bet.wager*bet.bets > bet.profit then 1 else null end) as lost_bets,
+					COUNT(case when bet.wager*bet.bets <= bet.profit then 1 else null end) as won_bets,
+                    SUM((bet.wager/power(10::numeric, token.decimals))*bet.bets*price.price) as total_wagered_sum,
+					SUM((bet.profit/power(10::numeric, token.decimals))*price.price) as gross_profit,
+					SUM((bet.profit/power(10::numeric, token.decimals))*price.price)-SUM((bet.wager/power(10::numeric, token.decimals))*bet.bets*price.price) as net_profit,
+					MAX((bet.profit/power(10::numeric, token.decimals))*price.price) as highest_win
+            FROM bet 
+			INNER JOIN token
+			    ON bet.token_address = token.contract_address
+			CROSS JOIN LATERAL (SELECT token_price_at(token.name, bet.timestamp) AS price) AS price
+            INNER JOIN ConnectedWallets 
+                ON bet.player=ConnectedWallets.address 
+                    AND ConnectedWallets.partner_id=$1
+            "#,
+            partner_wallet
+        )
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    pub async fn query_last_block(
+        &self,
+        network_id: i64,
+    ) -> Result<Option<LastBlock>, sqlx::Error> {
+        sqlx::query_as_unchecked!(
+            LastBlock,
+            r#"
+            SELECT * FROM LastBlock WHERE network_id=$1
+            "#,
+            network_id
+        )
+        .fetch_optional(&self.db_pool)
+        .await
+    }
+
+    /// Computes the multipliers of the bets stored before they were derived from the payout,
+    /// those are the bets of unknown blocks, then the aggregates of their players,
+    /// returns the amount of updated bets
+    pub async fn recompute_legacy_multipliers(&self) -> Result<u64, sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let updated = sqlx::query!(
+            "
+            UPDATE Bet
+            SET multiplier = CASE
+                WHEN wager * bets > 0 THEN (profit / (wager * bets))::DOUBLE PRECISION
+                ELSE 0
+            END
+            WHERE block_number = 0
+            "
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        let players = sqlx::query_scalar!(
+            r#"
+            SELECT DISTINCT player AS "player!"
+            FROM Bet
+            WHERE block_number = 0
+            "#
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        refresh_players(&mut tx, &players).await?;
+
+        tx.commit().await?;
+
+        Ok(updated)
+    }
+
+    pub async fn query_pending_backfill_jobs(
+        &self,
+        network_id: i64,
+    ) -> Result<Vec<BackfillJob>, sqlx::Error> {
+        sqlx::query_as_unchecked!(
+            BackfillJob,
+            r#"
+            SELECT * FROM BackfillJob
+            WHERE network_id=$1 AND NOT done
+            ORDER BY id
+            "#,
+            network_id
+        )
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    pub async fn set_backfill_progress(
+        &self,
+        job_id: i64,
+        processed_block: i64,
+        done: bool,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "
+            UPDATE BackfillJob
+            SET processed_block = $2, done = $3
+            WHERE id = $1
+            ",
+            job_id,
+            processed_block,
+            done
+        )
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn query_recent_bet_blocks(
+        &self,
+        network_id: i64,
+        limit: i64,
+    ) -> Result<Vec<BetBlock>, sqlx::Error> {
+        sqlx::query_as_unchecked!(
+            BetBlock,
+            r#"
+            SELECT DISTINCT block_number, block_hash
+            FROM Bet
+            WHERE network_id=$1
+            ORDER BY block_number DESC
+            LIMIT $2
+            "#,
+            network_id,
+            limit
+        )
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    /// Removes bets of the network placed in the orphaned blocks starting from `from_block`
+    /// with the aggregates of their players and moves the network cursor back,
+    /// returns the removed bets
+    pub async fn revert_bets(
+        &self,
+        network_id: i64,
+        from_block: i64,
+    ) -> Result<Vec<BetInfo>, sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let bets = sqlx::query_as_unchecked!(
+            BetInfo,
+            r#"
+            SELECT BetInfo.*
+            FROM BetInfo
+            INNER JOIN Bet ON BetInfo.id = Bet.id
+            WHERE Bet.network_id=$1 AND Bet.block_number >= $2
+            "#,
+            network_id,
+            from_block
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM Bet
+            WHERE network_id=$1 AND block_number >= $2
+            ",
+            network_id,
+            from_block
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let mut players: Vec<String> = bets.iter().map(|bet| bet.player.clone()).collect();
+        players.sort_unstable();
+        players.dedup();
+        refresh_players(&mut tx, &players).await?;
+
+        // the orphaned logs aren't worth processing again
+        sqlx::query!(
+            "
+            DELETE FROM LogDeadLetter
+            WHERE network_id=$1 AND block_number >= $2
+            ",
+            network_id,
+            from_block
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            INSERT INTO LastBlock(id, network_id)
+            VALUES ($1, $2)
+            ON CONFLICT(network_id) DO UPDATE
+                SET id = LEAST(LastBlock.id, excluded.id)
+            ",
+            from_block,
+            network_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(bets)
+    }
+
+    pub async fn add_network(&self, network: &NetworkInfo) -> Result<(), sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let currency_id = sqlx::query_scalar!(
+            "
+            INSERT INTO NativeCurrency(name, symbol, decimals)
+            VALUES ($1, $2, $3)
+            RETURNING id
+            ",
+            network.currency_name,
+            network.currency_symbol,
+            network.decimals
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            INSERT INTO Network(id, name, short_name, native_currency_id, confirmations)
+            VALUES ($1, $2, $3, $4, $5)
+            ",
+            network.network_id,
+            network.network_name,
+            network.short_name,
+            currency_id,
+            network.confirmations
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await
+    }
+
+    /// Returns whether the network exists
+    pub async fn update_network(&self, network: &NetworkInfo) -> Result<bool, sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let currency_id = sqlx::query_scalar!(
+            "
+            UPDATE Network
+            SET name = $2,
+                short_name = $3,
+                confirmations = $4
+            WHERE id = $1
+            RETURNING native_currency_id
+            ",
+            network.network_id,
+            network.network_name,
+            network.short_name,
+            network.confirmations
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let currency_id = match currency_id {
+            Some(id) => id,
+            None => return Ok(false),
+        };
+
+        sqlx::query!(
+            "
+            UPDATE NativeCurrency
+            SET name = $2,
+                symbol = $3,
+                decimals = $4
+            WHERE id = $1
+            ",
+            currency_id,
+            network.currency_name,
+            network.currency_symbol,
+            network.decimals
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Fails while the network still has rpcs, tokens, games or bets,
+    /// returns whether the network existed
+    pub async fn delete_network(&self, network_id: i64) -> Result<bool, sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+
+        sqlx::query!("DELETE FROM LastBlock WHERE network_id = $1", network_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let currency_id = sqlx::query_scalar!(
+            "DELETE FROM Network WHERE id = $1 RETURNING native_currency_id",
+            network_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let currency_id = match currency_id {
+            Some(id) => id,
+            None => return Ok(false),
+        };
+
+        sqlx::query!("DELETE FROM NativeCurrency WHERE id = $1", currency_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn add_rpc(&self, network_id: i64, url: &str) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar!(
+            "INSERT INTO RpcUrl(network_id, url) VALUES ($1, $2) RETURNING id",
+            network_id,
+            url
+        )
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    /// Returns the network of the rpc before the update
+    pub async fn update_rpc(
+        &self,
+        id: i64,
+        network_id: i64,
+        url: &str,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        sqlx::query_scalar!(
+            "
+            UPDATE RpcUrl
+            SET network_id = $2,
+                url = $3
+            FROM RpcUrl AS old
+            WHERE RpcUrl.id = $1 AND old.id = $1
+            RETURNING old.network_id
+            ",
+            id,
+            network_id,
+            url
+        )
+        .fetch_optional(&self.db_pool)
+        .await
+    }
+
+    /// Returns the network of the removed rpc
+    pub async fn delete_rpc(&self, id: i64) -> Result<Option<i64>, sqlx::Error> {
+        sqlx::query_scalar!("DELETE FROM RpcUrl WHERE id = $1 RETURNING network_id", id)
+            .fetch_optional(&self.db_pool)
+            .await
+    }
+
+    pub async fn add_block_explorer(&self, network_id: i64, url: &str) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar!(
+            "INSERT INTO BlockExplorerUrl(network_id, url) VALUES ($1, $2) RETURNING id",
+            network_id,
+            url
+        )
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    /// Returns whether the block explorer exists
+    pub async fn update_block_explorer(
+        &self,
+        id: i64,
+        network_id: i64,
+        url: &str,
+    ) -> Result<bool, sqlx::Error> {
+        sqlx::query!(
+            "
+            UPDATE BlockExplorerUrl
+            SET network_id = $2,
+                url = $3
+            WHERE id = $1
             ",
-            bet.transaction_hash,
-            bet.player,
-            bet.timestamp.naive_utc(),
-            bet.game_id,
-            bet.wager,
-            bet.token_address,
-            bet.network_id,
-            bet.bets,
-            bet.multiplier,
-            bet.profit,
+            id,
+            network_id,
+            url
         )
         .execute(&self.db_pool)
         .await
-        .map(|_| ())
+        .map(|result| result.rows_affected() > 0)
     }
 
-    pub async fn query_abi(&self, signature: &str) -> Result<GameAbi, sqlx::Error> {
-        sqlx::query_as_unchecked!(
-            GameAbi,
+    /// Returns whether the block explorer existed
+    pub async fn delete_block_explorer(&self, id: i64) -> Result<bool, sqlx::Error> {
+        sqlx::query!("DELETE FROM BlockExplorerUrl WHERE id = $1", id)
+            .execute(&self.db_pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+    }
+
+    pub async fn add_token(&self, token: &NewToken) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar!(
             "
-            SELECT * FROM GameAbi WHERE signature=$1 LIMIT 1
+            INSERT INTO Token(network_id, name, contract_address, decimals)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id
             ",
-            signature
+            token.network_id,
+            token.name,
+            token.contract_address,
+            token.decimals
         )
         .fetch_one(&self.db_pool)
         .await
     }
 
-    pub async fn query_player_totals(&self, address: &str) -> Result<PlayerTotals, sqlx::Error> {
-        sqlx::query_as_unchecked!(
-            PlayerTotals,
-            r#"
-            SELECT 
-                    COUNT(bet.id) AS bets_amount,
-                    COUNT(case when bet.wager*bet.bets > bet.profit then 1 else null end) as lost_bets,
-					COUNT(case when bet.wager*bet.bets <= bet.profit then 1 else null end) as won_bets,
-                    SUM((bet.wager/1000000000000000000)*bet.bets*price.price) as total_wagered_sum,
-					SUM((bet.profit/1000000000000000000)*price.price) as gross_profit,
-					SUM((bet.profit/1000000000000000000)*price.price)-SUM((bet.wager/1000000000000000000)*bet.bets*price.price) as net_profit,
-					MAX((bet.profit/1000000000000000000)*price.price) as highest_win
-            FROM bet 
-			INNER JOIN (SELECT 
-                                token.name AS name,
-                                token.contract_address AS address,
-                                tokenprice.price AS price
-                        FROM token
-                        INNER JOIN tokenprice ON token.name=tokenprice.token_name) AS price
-              ON bet.token_address = price.address AND bet.player = $1
-            "#,
-            address
+    /// Returns the network of the token before the update
+    pub async fn update_token(
+        &self,
+        id: i64,
+        token: &NewToken,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        sqlx::query_scalar!(
+            "
+            UPDATE Token
+            SET network_id = $2,
+                name = $3,
+                contract_address = $4,
+                decimals = $5
+            FROM Token AS old
+            WHERE Token.id = $1 AND old.id = $1
+            RETURNING old.network_id
+            ",
+            id,
+            token.network_id,
+            token.name,
+            token.contract_address,
+            token.decimals
         )
-        .fetch_one(&self.db_pool)
+        .fetch_optional(&self.db_pool)
         .await
     }
 
-    pub async fn query_players_totals(
-        &self,
-        partner_wallet: &str,
-    ) -> Result<PlayersTotals, sqlx::Error> {
-        sqlx::query_as_unchecked!(
-            PlayersTotals,
-            r#"
-            SELECT 
-                    COUNT(bet.id) AS bets_amount,
-                    COUNT(case when bet.wager*bet.bets > bet.profit then 1 else null end) as lost_bets,
-					COUNT(case when bet.wager*bet.bets <= bet.profit then 1 else null end) as won_bets,
-                    SUM((bet.wager/1000000000000000000)*bet.bets*price.price) as total_wagered_sum,
-					SUM((bet.profit/1000000000000000000)*price.price) as gross_profit,
-					SUM((bet.profit/1000000000000000000)*price.price)-SUM((bet.wager/1000000000000000000)*bet.bets*price.price) as net_profit,
-					MAX((bet.profit/1000000000000000000)*price.price) as highest_win
-            FROM bet 
-			INNER JOIN (SELECT 
-                                token.name AS name,
-                                token.contract_address AS address,
-                                tokenprice.price AS price
-                        FROM token
-                        INNER JOIN tokenprice ON token.name=tokenprice.token_name) AS price
-            ON bet.token_address = price.address
-            INNER JOIN ConnectedWallets 
-                ON bet.player=ConnectedWallets.address 
-                    AND ConnectedWallets.partner_id=$1
-            "#,
-            partner_wallet
+    /// Returns the network of the removed token
+    pub async fn delete_token(&self, id: i64) -> Result<Option<i64>, sqlx::Error> {
+        sqlx::query_scalar!("DELETE FROM Token WHERE id = $1 RETURNING network_id", id)
+            .fetch_optional(&self.db_pool)
+            .await
+    }
+
+    pub async fn add_game(&self, game: &NewGame) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar!(
+            "
+            INSERT INTO Game(network_id, name, address, result_event_signature, multiplier_precision)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id
+            ",
+            game.network_id,
+            game.name,
+            game.address,
+            game.result_event_signature,
+            game.multiplier_precision
         )
         .fetch_one(&self.db_pool)
         .await
     }
 
-    pub async fn query_last_block(
-        &self,
-        network_id: i64,
-    ) -> Result<Option<LastBlock>, sqlx::Error> {
-        sqlx::query_as_unchecked!(
-            LastBlock,
-            r#"
-            SELECT * FROM LastBlock WHERE network_id=$1
-            "#,
-            network_id
+    /// Returns the network of the game before the update
+    pub async fn update_game(&self, id: i64, game: &NewGame) -> Result<Option<i64>, sqlx::Error> {
+        sqlx::query_scalar!(
+            "
+            UPDATE Game
+            SET network_id = $2,
+                name = $3,
+                address = $4,
+                result_event_signature = $5,
+                multiplier_precision = $6
+            FROM Game AS old
+            WHERE Game.id = $1 AND old.id = $1
+            RETURNING old.network_id
+            ",
+            id,
+            game.network_id,
+            game.name,
+            game.address,
+            game.result_event_signature,
+            game.multiplier_precision
         )
         .fetch_optional(&self.db_pool)
         .await
     }
 
-    pub async fn set_last_block(&self, network_id: i64, block_id: i64) -> Result<(), sqlx::Error> {
+    /// Fails while the game has bets, returns the network of the removed game
+    pub async fn delete_game(&self, id: i64) -> Result<Option<i64>, sqlx::Error> {
+        sqlx::query_scalar!("DELETE FROM Game WHERE id = $1 RETURNING network_id", id)
+            .fetch_optional(&self.db_pool)
+            .await
+    }
+
+    pub async fn add_game_abi(&self, abi: &GameAbi) -> Result<(), sqlx::Error> {
         sqlx::query!(
             "
-            INSERT INTO LastBlock(id, network_id)
-            VALUES ($1, $2)
-            ON CONFLICT(network_id) DO UPDATE
-                SET id = excluded.id
+            INSERT INTO GameAbi(signature, types, names, event, abi)
+            VALUES ($1, $2, $3, $4, $5)
             ",
-            block_id,
-            network_id,
+            abi.signature,
+            abi.types,
+            abi.names,
+            abi.event,
+            abi.abi
         )
         .execute(&self.db_pool)
         .await?;
@@ -639,6 +1271,56 @@ impl DB {
         Ok(())
     }
 
+    /// Returns the networks of the games using the abi,
+    /// `None` if there's no abi with the signature
+    pub async fn update_game_abi(&self, abi: &GameAbi) -> Result<Option<Vec<i64>>, sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let updated = sqlx::query!(
+            "
+            UPDATE GameAbi
+            SET types = $2,
+                names = $3,
+                event = $4,
+                abi = $5
+            WHERE signature = $1
+            ",
+            abi.signature,
+            abi.types,
+            abi.names,
+            abi.event,
+            abi.abi
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            return Ok(None);
+        }
+
+        let networks = sqlx::query_scalar!(
+            r#"
+            SELECT DISTINCT network_id AS "network_id!"
+            FROM Game
+            WHERE result_event_signature = $1
+            "#,
+            abi.signature
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(networks))
+    }
+
+    /// Fails while games use the abi, returns whether the abi existed
+    pub async fn delete_game_abi(&self, signature: &str) -> Result<bool, sqlx::Error> {
+        sqlx::query!("DELETE FROM GameAbi WHERE signature = $1", signature)
+            .execute(&self.db_pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+    }
+
     pub async fn create_referal(&self, refer_to: &str, referal: &str) -> Result<(), sqlx::Error> {
         sqlx::query!(
             "
@@ -716,19 +1398,21 @@ impl DB {
         .await
     }
 
-    pub async fn get_partner_by_login(&self, login: &str) -> Result<Partner, sqlx::Error> {
-        sqlx::query_as_unchecked!(
-            Partner,
+    pub async fn get_partner_token_version(
+        &self,
+        wallet: &str,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        sqlx::query!(
             r#"
-            SELECT * 
+            SELECT token_version
             FROM Partner
-            WHERE login=$1
-            LIMIT 1
+            WHERE main_wallet=$1
             "#,
-            login
+            wallet
         )
-        .fetch_one(&self.db_pool)
+        .fetch_optional(&self.db_pool)
         .await
+        .map(|r| r.map(|r| r.token_version))
     }
 
     pub async fn create_withdraw_request(
@@ -831,21 +1515,35 @@ impl DB {
         }
     }
 
-    pub async fn login_partner(
+    /// Partner by the main wallet in any letter case
+    pub async fn get_partner_by_wallet(
         &self,
-        login: &str,
-        password: &str,
+        wallet: &str,
     ) -> Result<Option<Partner>, sqlx::Error> {
+        sqlx::query_as_unchecked!(
+            Partner,
+            r#"
+            SELECT *
+            FROM Partner
+            WHERE LOWER(main_wallet)=LOWER($1)
+            LIMIT 1
+            "#,
+            wallet
+        )
+        .fetch_optional(&self.db_pool)
+        .await
+    }
+
+    pub async fn get_partner_by_login(&self, login: &str) -> Result<Option<Partner>, sqlx::Error> {
         sqlx::query_as_unchecked!(
             Partner,
             r#"
             SELECT * 
             FROM Partner
-            WHERE login=$1 AND password=$2
+            WHERE login=$1
             LIMIT 1
             "#,
-            login,
-            password
+            login
         )
         .fetch_optional(&self.db_pool)
         .await
@@ -878,25 +1576,248 @@ impl DB {
         .map(|_| ())
     }
 
+    /// Replaces the password hash and revokes the issued access tokens,
+    /// fails if the stored hash isn't `old_hash` anymore
     pub async fn partner_change_password(
         &self,
         wallet: &str,
-        old_password: &str,
-        new_password: &str,
+        old_hash: &str,
+        new_hash: &str,
     ) -> Result<bool, sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE Partner
+            SET password=$1,
+                token_version=token_version + 1
+            WHERE main_wallet=$2 AND password=$3
+            "#,
+            new_hash,
+            wallet,
+            old_hash
+        )
+        .execute(&self.db_pool)
+        .await
+        .map(|r| r.rows_affected() > 0)
+    }
+
+    /// Rehashes the password without revoking the sessions
+    pub async fn upgrade_partner_password(
+        &self,
+        wallet: &str,
+        old_hash: &str,
+        new_hash: &str,
+    ) -> Result<(), sqlx::Error> {
         sqlx::query!(
             r#"
             UPDATE Partner
             SET password=$1
             WHERE main_wallet=$2 AND password=$3
             "#,
-            new_password,
+            new_hash,
+            wallet,
+            old_hash
+        )
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn add_refresh_token(
+        &self,
+        wallet: &str,
+        token_hash: &str,
+        family: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO RefreshToken(token_hash, family, partner_id, expires_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+            token_hash,
+            family,
             wallet,
-            old_password
+            expires_at.naive_utc()
+        )
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Marks the token as used and stores the next one of its family,
+    /// returns the wallet of the partner if the token was still valid
+    pub async fn rotate_refresh_token(
+        &self,
+        token_hash: &str,
+        new_token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+        let rotated = sqlx::query!(
+            r#"
+            UPDATE RefreshToken
+            SET used=TRUE
+            WHERE token_hash=$1
+                AND NOT used
+                AND NOT revoked
+                AND expires_at > $2
+            RETURNING partner_id, family
+            "#,
+            token_hash,
+            Utc::now().naive_utc()
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(rotated) = rotated else {
+            return Ok(None);
+        };
+
+        sqlx::query!(
+            r#"
+            INSERT INTO RefreshToken(token_hash, family, partner_id, expires_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+            new_token_hash,
+            rotated.family,
+            rotated.partner_id,
+            expires_at.naive_utc()
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(Some(rotated.partner_id))
+    }
+
+    pub async fn query_refresh_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<RefreshToken>, sqlx::Error> {
+        sqlx::query_as_unchecked!(
+            RefreshToken,
+            r#"
+            SELECT *
+            FROM RefreshToken
+            WHERE token_hash=$1
+            "#,
+            token_hash
+        )
+        .fetch_optional(&self.db_pool)
+        .await
+    }
+
+    pub async fn revoke_refresh_token_family(&self, family: &str) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE RefreshToken
+            SET revoked=TRUE
+            WHERE family=$1
+            "#,
+            family
+        )
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn revoke_partner_refresh_tokens(&self, wallet: &str) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE RefreshToken
+            SET revoked=TRUE
+            WHERE partner_id=$1
+            "#,
+            wallet
+        )
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Stores a new nonce and drops the expired ones
+    pub async fn add_siwe_nonce(
+        &self,
+        nonce: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+        sqlx::query!(
+            r#"
+            DELETE FROM SiweNonce
+            WHERE expires_at <= $1
+            "#,
+            Utc::now().naive_utc()
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO SiweNonce(nonce, expires_at)
+            VALUES ($1, $2)
+            "#,
+            nonce,
+            expires_at.naive_utc()
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await
+    }
+
+    /// Deletes the nonce, returns false if it was unknown, already used or expired
+    pub async fn consume_siwe_nonce(&self, nonce: &str) -> Result<bool, sqlx::Error> {
+        sqlx::query!(
+            r#"
+            DELETE FROM SiweNonce
+            WHERE nonce=$1 AND expires_at > $2
+            "#,
+            nonce,
+            Utc::now().naive_utc()
         )
         .execute(&self.db_pool)
         .await
-        .map(|r| (r.rows_affected() > 0))
+        .map(|r| r.rows_affected() > 0)
+    }
+
+    /// Remembers the nonce of a signed request and drops the expired ones,
+    /// returns false if the wallet has already used the nonce
+    pub async fn consume_signature_nonce(
+        &self,
+        wallet: &str,
+        nonce: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+        sqlx::query!(
+            r#"
+            DELETE FROM SignatureNonce
+            WHERE expires_at <= $1
+            "#,
+            Utc::now().naive_utc()
+        )
+        .execute(&mut *tx)
+        .await?;
+        let consumed = sqlx::query!(
+            r#"
+            INSERT INTO SignatureNonce(wallet, nonce, expires_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING
+            "#,
+            wallet,
+            nonce,
+            expires_at.naive_utc()
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected()
+            > 0;
+        tx.commit().await?;
+
+        Ok(consumed)
     }
 
     pub async fn add_partner_contacts(
@@ -1580,16 +2501,12 @@ impl DB {
                 SELECT bet.player, bet.total, nickname.nickname from (
                     SELECT 
                         bet.player,
-                        SUM((bet.wager/1000000000000000000)*bet.bets*price.price) as total
+                        SUM((bet.wager/power(10::numeric, token.decimals))*bet.bets*price.price) as total
                     FROM bet
-                    INNER JOIN (SELECT 
-                                    token.name AS name,
-                                    token.contract_address AS address,
-                                    tokenprice.price AS price
-                                FROM token
-                                INNER JOIN tokenprice ON token.name=tokenprice.token_name) AS price
-                    ON bet.token_address = price.address
-                    WHERE bet.timestamp > now() - interval '1 day'
+                    INNER JOIN token
+                        ON bet.token_address = token.contract_address
+                    CROSS JOIN LATERAL (SELECT token_price_at(token.name, bet.timestamp) AS price) AS price
+                    WHERE bet.timestamp > now() - interval '1 day' AND price.price IS NOT NULL
                     GROUP BY bet.player) as bet
                     LEFT JOIN nickname ON bet.player=nickname.address
                     ORDER BY total DESC
@@ -1607,16 +2524,12 @@ impl DB {
                 SELECT bet.player, bet.total, nickname.nickname from (
                     SELECT 
                         bet.player,
-                        SUM((bet.wager/1000000000000000000)*bet.bets*price.price) as total
+                        SUM((bet.wager/power(10::numeric, token.decimals))*bet.bets*price.price) as total
                     FROM bet
-                    INNER JOIN (SELECT 
-                                    token.name AS name,
-                                    token.contract_address AS address,
-                                    tokenprice.price AS price
-                                FROM token
-                                INNER JOIN tokenprice ON token.name=tokenprice.token_name) AS price
-                    ON bet.token_address = price.address
-                    WHERE bet.timestamp > now() - interval '1 week'
+                    INNER JOIN token
+                        ON bet.token_address = token.contract_address
+                    CROSS JOIN LATERAL (SELECT token_price_at(token.name, bet.timestamp) AS price) AS price
+                    WHERE bet.timestamp > now() - interval '1 week' AND price.price IS NOT NULL
                     GROUP BY bet.player) as bet
                     LEFT JOIN nickname ON bet.player=nickname.address
                     ORDER BY total DESC
@@ -1634,16 +2547,12 @@ impl DB {
                 SELECT bet.player, bet.total, nickname.nickname from (
                     SELECT 
                         bet.player,
-                        SUM((bet.wager/1000000000000000000)*bet.bets*price.price) as total
+                        SUM((bet.wager/power(10::numeric, token.decimals))*bet.bets*price.price) as total
                     FROM bet
-                    INNER JOIN (SELECT 
-                                    token.name AS name,
-                                    token.contract_address AS address,
-                                    tokenprice.price AS price
-                                FROM token
-                                INNER JOIN tokenprice ON token.name=tokenprice.token_name) AS price
-                    ON bet.token_address = price.address
-                    WHERE bet.timestamp > now() - interval '1 month'
+                    INNER JOIN token
+                        ON bet.token_address = token.contract_address
+                    CROSS JOIN LATERAL (SELECT token_price_at(token.name, bet.timestamp) AS price) AS price
+                    WHERE bet.timestamp > now() - interval '1 month' AND price.price IS NOT NULL
                     GROUP BY bet.player) as bet
                     LEFT JOIN nickname ON bet.player=nickname.address
                     ORDER BY total DESC
@@ -1661,15 +2570,12 @@ impl DB {
                 SELECT bet.player, bet.total, nickname.nickname from (
                     SELECT 
                         bet.player,
-                        SUM((bet.wager/1000000000000000000)*bet.bets*price.price) as total
+                        SUM((bet.wager/power(10::numeric, token.decimals))*bet.bets*price.price) as total
                     FROM bet
-                    INNER JOIN (SELECT 
-                                    token.name AS name,
-                                    token.contract_address AS address,
-                                    tokenprice.price AS price
-                                FROM token
-                                INNER JOIN tokenprice ON token.name=tokenprice.token_name) AS price
-                    ON bet.token_address = price.address
+                    INNER JOIN token
+                        ON bet.token_address = token.contract_address
+                    CROSS JOIN LATERAL (SELECT token_price_at(token.name, bet.timestamp) AS price) AS price
+                    WHERE price.price IS NOT NULL
                     GROUP BY bet.player) as bet
                     LEFT JOIN nickname ON bet.player=nickname.address
                     ORDER BY total DESC
@@ -1696,16 +2602,12 @@ impl DB {
                 SELECT bet.player, bet.total, nickname.nickname from (
                     SELECT 
                         bet.player,
-                        SUM((bet.profit/1000000000000000000)*price.price) as total
+                        SUM((bet.profit/power(10::numeric, token.decimals))*price.price) as total
                     FROM bet
-                    INNER JOIN (SELECT 
-                                    token.name AS name,
-                                    token.contract_address AS address,
-                                    tokenprice.price AS price
-                                FROM token
-                                INNER JOIN tokenprice ON token.name=tokenprice.token_name) AS price
-                    ON bet.token_address = price.address
-                    WHERE bet.timestamp > now() - interval '1 day'
+                    INNER JOIN token
+                        ON bet.token_address = token.contract_address
+                    CROSS JOIN LATERAL (SELECT token_price_at(token.name, bet.timestamp) AS price) AS price
+                    WHERE bet.timestamp > now() - interval '1 day' AND price.price IS NOT NULL
                     GROUP BY bet.player) as bet
                     LEFT JOIN nickname ON bet.player=nickname.address
                     ORDER BY total DESC
@@ -1723,16 +2625,12 @@ impl DB {
                 SELECT bet.player, bet.total, nickname.nickname from (
                     SELECT 
                         bet.player,
-                        SUM((bet.profit/1000000000000000000)*price.price) as total
+                        SUM((bet.profit/power(10::numeric, token.decimals))*price.price) as total
                     FROM bet
-                    INNER JOIN (SELECT 
-                                    token.name AS name,
-                                    token.contract_address AS address,
-                                    tokenprice.price AS price
-                                FROM token
-                                INNER JOIN tokenprice ON token.name=tokenprice.token_name) AS price
-                    ON bet.token_address = price.address
-                    WHERE bet.timestamp > now() - interval '1 week'
+                    INNER JOIN token
+                        ON bet.token_address = token.contract_address
+                    CROSS JOIN LATERAL (SELECT token_price_at(token.name, bet.timestamp) AS price) AS price
+                    WHERE bet.timestamp > now() - interval '1 week' AND price.price IS NOT NULL
                     GROUP BY bet.player) as bet
                     LEFT JOIN nickname ON bet.player=nickname.address
                     ORDER BY total DESC
@@ -1750,16 +2648,12 @@ impl DB {
                 SELECT bet.player, bet.total, nickname.nickname from (
                     SELECT 
                         bet.player,
-                        SUM((bet.profit/1000000000000000000)*price.price) as total
+                        SUM((bet.profit/power(10::numeric, token.decimals))*price.price) as total
                     FROM bet
-                    INNER JOIN (SELECT 
-                                    token.name AS name,
-                                    token.contract_address AS address,
-                                    tokenprice.price AS price
-                                FROM token
-                                INNER JOIN tokenprice ON token.name=tokenprice.token_name) AS price
-                    ON bet.token_address = price.address
-                    WHERE bet.timestamp > now() - interval '1 month'
+                    INNER JOIN token
+                        ON bet.token_address = token.contract_address
+                    CROSS JOIN LATERAL (SELECT token_price_at(token.name, bet.timestamp) AS price) AS price
+                    WHERE bet.timestamp > now() - interval '1 month' AND price.price IS NOT NULL
                     GROUP BY bet.player) as bet
                     LEFT JOIN nickname ON bet.player=nickname.address
                     ORDER BY total DESC
@@ -1777,15 +2671,12 @@ impl DB {
                 SELECT bet.player, bet.total, nickname.nickname from (
                     SELECT 
                         bet.player,
-                        SUM((bet.profit/1000000000000000000)*price.price) as total
+                        SUM((bet.profit/power(10::numeric, token.decimals))*price.price) as total
                     FROM bet
-                    INNER JOIN (SELECT 
-                                    token.name AS name,
-                                    token.contract_address AS address,
-                                    tokenprice.price AS price
-                                FROM token
-                                INNER JOIN tokenprice ON token.name=tokenprice.token_name) AS price
-                    ON bet.token_address = price.address
+                    INNER JOIN token
+                        ON bet.token_address = token.contract_address
+                    CROSS JOIN LATERAL (SELECT token_price_at(token.name, bet.timestamp) AS price) AS price
+                    WHERE price.price IS NOT NULL
                     GROUP BY bet.player) as bet
                     LEFT JOIN nickname ON bet.player=nickname.address
                     ORDER BY total DESC
@@ -1799,3 +2690,218 @@ impl DB {
         }
     }
 }
+
+/// Inserts the bet with its game result, a bet of the same log is overwritten
+async fn insert_bet(conn: &mut PgConnection, bet: &Bet) -> Result<(), sqlx::Error> {
+    let stored = sqlx::query!(
+        r#"
+        INSERT INTO Bet(
+            transaction_hash,
+            player,
+            timestamp,
+            game_id,
+            wager,
+            token_address,
+            network_id,
+            bets,
+            multiplier,
+            profit,
+            block_number,
+            block_hash,
+            log_index
+        ) VALUES (
+            $1,
+            $2,
+            $3,
+            $4,
+            $5,
+            $6,
+            $7,
+            $8,
+            $9,
+            $10,
+            $11,
+            $12,
+            $13
+        )
+        ON CONFLICT(network_id, transaction_hash, log_index) DO UPDATE
+            SET player = excluded.player,
+                game_id = excluded.game_id,
+                wager = excluded.wager,
+                token_address = excluded.token_address,
+                bets = excluded.bets,
+                multiplier = excluded.multiplier,
+                profit = excluded.profit,
+                block_number = excluded.block_number,
+                block_hash = excluded.block_hash,
+                timestamp = excluded.timestamp
+        RETURNING id, (xmax = 0) AS "inserted!"
+        "#,
+        bet.transaction_hash,
+        bet.player,
+        bet.timestamp.naive_utc(),
+        bet.game_id,
+        bet.wager,
+        bet.token_address,
+        bet.network_id,
+        bet.bets,
+        bet.multiplier,
+        bet.profit,
+        bet.block_number,
+        bet.block_hash,
+        bet.log_index,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    let bet_id = stored.id;
+
+    if let Some(game_result) = &bet.game_result {
+        sqlx::query!(
+            "
+            INSERT INTO BetResult(bet_id, result)
+            VALUES ($1, $2)
+            ON CONFLICT(bet_id) DO UPDATE
+                SET result = excluded.result
+            ",
+            bet_id,
+            Json(game_result) as _,
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    // an overwritten bet is already counted, only its highest values may change
+    sqlx::query!(
+        "
+        INSERT INTO Player(
+            address,
+            wagered,
+            bets,
+            bets_won,
+            bets_lost,
+            highest_win,
+            highest_multiplier
+        )
+        SELECT
+            bet.player,
+            CASE WHEN $2
+                THEN COALESCE((bet.wager/power(10::numeric, token.decimals))*bet.bets*price.price, 0)
+                ELSE 0
+            END::DOUBLE PRECISION,
+            CASE WHEN $2 THEN 1 ELSE 0 END,
+            CASE WHEN $2 AND bet.wager*bet.bets <= bet.profit THEN 1 ELSE 0 END,
+            CASE WHEN $2 AND bet.wager*bet.bets > bet.profit THEN 1 ELSE 0 END,
+            COALESCE((bet.profit/power(10::numeric, token.decimals))*price.price, 0)::DOUBLE PRECISION,
+            bet.multiplier
+        FROM Bet bet
+        LEFT JOIN Token token
+            ON bet.token_address = token.contract_address AND bet.network_id = token.network_id
+        LEFT JOIN LATERAL (SELECT token_price_at(token.name, bet.timestamp) AS price) AS price
+            ON TRUE
+        WHERE bet.id = $1
+        ON CONFLICT(address) DO UPDATE
+            SET wagered = Player.wagered + excluded.wagered,
+                bets = Player.bets + excluded.bets,
+                bets_won = Player.bets_won + excluded.bets_won,
+                bets_lost = Player.bets_lost + excluded.bets_lost,
+                highest_win = GREATEST(Player.highest_win, excluded.highest_win),
+                highest_multiplier = GREATEST(Player.highest_multiplier, excluded.highest_multiplier)
+        ",
+        bet_id,
+        stored.inserted,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Recomputes the aggregates of the players from their stored bets
+async fn refresh_players(conn: &mut PgConnection, players: &[String]) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "
+        INSERT INTO Player(
+            address,
+            wagered,
+            bets,
+            bets_won,
+            bets_lost,
+            highest_win,
+            highest_multiplier
+        )
+        SELECT
+            player.address,
+            COALESCE(
+                SUM((bet.wager/power(10::numeric, token.decimals))*bet.bets*price.price),
+                0
+            )::DOUBLE PRECISION,
+            COUNT(bet.id),
+            COUNT(bet.id) FILTER (WHERE bet.wager*bet.bets <= bet.profit),
+            COUNT(bet.id) FILTER (WHERE bet.wager*bet.bets > bet.profit),
+            COALESCE(MAX((bet.profit/power(10::numeric, token.decimals))*price.price), 0)::DOUBLE PRECISION,
+            COALESCE(MAX(bet.multiplier), 0)
+        FROM UNNEST($1::character(42)[]) AS player(address)
+        LEFT JOIN Bet bet ON bet.player = player.address
+        LEFT JOIN Token token
+            ON bet.token_address = token.contract_address AND bet.network_id = token.network_id
+        LEFT JOIN LATERAL (SELECT token_price_at(token.name, bet.timestamp) AS price) AS price
+            ON TRUE
+        GROUP BY player.address
+        ON CONFLICT(address) DO UPDATE
+            SET wagered = excluded.wagered,
+                bets = excluded.bets,
+                bets_won = excluded.bets_won,
+                bets_lost = excluded.bets_lost,
+                highest_win = excluded.highest_win,
+                highest_multiplier = excluded.highest_multiplier
+        ",
+        players,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Stores the dead letter, the reason of an already stored log is updated
+async fn insert_log_dead_letter(
+    conn: &mut PgConnection,
+    dead_letter: &LogDeadLetter,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "
+        INSERT INTO LogDeadLetter(
+            network_id,
+            block_number,
+            block_hash,
+            transaction_hash,
+            log_index,
+            address,
+            topics,
+            data,
+            reason,
+            block_timestamp
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        ON CONFLICT(network_id, transaction_hash, log_index) DO UPDATE
+            SET block_number = excluded.block_number,
+                block_hash = excluded.block_hash,
+                reason = excluded.reason,
+                block_timestamp = excluded.block_timestamp,
+                timestamp = now()
+        ",
+        dead_letter.network_id,
+        dead_letter.block_number,
+        dead_letter.block_hash,
+        dead_letter.transaction_hash,
+        dead_letter.log_index,
+        dead_letter.address,
+        &dead_letter.topics,
+        dead_letter.data,
+        dead_letter.reason,
+        dead_letter.block_timestamp.map(|time| time.naive_utc()),
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
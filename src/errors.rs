@@ -1,3 +1,5 @@
+use crate::game_abi::GameAbiError;
+use crate::siwe::SiweError;
 use thiserror::Error;
 use warp::reject;
 
@@ -18,8 +20,8 @@ pub enum ApiError {
     #[error("{0}")]
     ArbitraryError(String),
 
-    // #[error("The auth signature is too old")]
-    // OldSignature,
+    #[error("The signature is too old or was already used")]
+    ReplayedSignature,
 
     // #[error("The wallet {0} is not registered")]
     // NotRegistered(String),
@@ -35,11 +37,38 @@ pub enum ApiError {
     #[error("Invalid authentication header")]
     InvalidAuthHeaderError,
 
-    #[error("Malformed token")]
-    MalformedToken,
-
     #[error("Bad password")]
     BadPassword,
+
+    #[error("Access token expired")]
+    TokenExpired,
+
+    #[error("Invalid access token: {0}")]
+    InvalidToken(String),
+
+    #[error("Invalid sign-in message: {0}")]
+    BadSiweMessage(SiweError),
+
+    #[error("The sign-in nonce is unknown, used or expired")]
+    BadNonce,
+
+    #[error("Invalid or expired refresh token")]
+    InvalidRefreshToken,
+
+    #[error("Refresh token was already used, the session is revoked")]
+    RefreshTokenReused,
+
+    #[error("Admin authorization required")]
+    NotAdmin,
+
+    #[error("Invalid game: {0}")]
+    InvalidGame(GameAbiError),
+
+    #[error("Token decimals `{0}` aren't between 0 and {1}")]
+    BadDecimals(i64, i64),
+
+    #[error("No {0} `{1}` was found")]
+    NotFound(&'static str, String),
 }
 
 impl reject::Reject for ApiError {}
@@ -1,14 +1,19 @@
+use crate::communication::RegistrySender;
 use crate::communication::WsDataFeedReceiver;
 use crate::communication::WsDataFeedSender;
-use crate::config::PASSWORD_SALT;
+use crate::communication::{SharedDbQueueMetrics, ShutdownReceiver};
+use crate::config::{
+    ADMIN_TOKEN, JWT_PARTNER_AUDIENCE, JWT_PLAYER_AUDIENCE, SIGNATURE_TTL, SIWE_DOMAIN, SIWE_ORIGIN,
+};
 use crate::db::DB;
 use crate::errors::ApiError;
 use crate::handlers;
-use crate::jwt;
-use crate::jwt::Payload;
-use crate::models::{db_models::TimeBoundaries, json_requests, LeaderboardType};
+use crate::jwt::{Payload, TokenError, JWT_KEYS};
+use crate::models::{db_models, db_models::TimeBoundaries, json_requests, LeaderboardType};
+use crate::rpc_pool::RpcHealthRegistry;
+use crate::siwe::{SiweError, SiweMessage};
 use crate::tools;
-use base64::{engine::general_purpose, Engine as _};
+use chrono::{TimeZone, Utc};
 use http::header::{HeaderMap, HeaderValue, AUTHORIZATION};
 use std::str;
 use tracing::debug;
@@ -20,35 +25,115 @@ fn with_db(db: DB) -> impl Filter<Extract = (DB,), Error = std::convert::Infalli
     warp::any().map(move || db.clone())
 }
 
+fn with_rpc_health(
+    rpc_health: RpcHealthRegistry,
+) -> impl Filter<Extract = (RpcHealthRegistry,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || rpc_health.clone())
+}
+
+fn with_db_queue(
+    db_queue: SharedDbQueueMetrics,
+) -> impl Filter<Extract = (SharedDbQueueMetrics,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || db_queue.clone())
+}
+
+fn with_registry(
+    registry: RegistrySender,
+) -> impl Filter<Extract = (RegistrySender,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || registry.clone())
+}
+
+fn with_shutdown(
+    shutdown: ShutdownReceiver,
+) -> impl Filter<Extract = (ShutdownReceiver,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || shutdown.clone())
+}
+
 fn with_channel(
     ch: WsDataFeedSender,
 ) -> impl Filter<Extract = (WsDataFeedReceiver,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || ch.subscribe())
 }
 
-async fn with_signature_nickname<'a>(
+/// Nonces can't contain spaces, so the fields of a signed message can't be shifted
+/// between the free form field and the nonce without changing the message
+fn is_valid_signature_nonce(nonce: &str) -> bool {
+    (8..=64).contains(&nonce.len()) && nonce.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+/// Rejects signatures older than `SIGNATURE_TTL` and nonces the wallet has already used
+async fn consume_signature_nonce(
+    db: &DB,
+    wallet: &str,
+    nonce: &str,
+    timestamp: u64,
+) -> Result<(), warp::Rejection> {
+    if !is_valid_signature_nonce(nonce) {
+        return Err(reject::custom(ApiError::ArbitraryError(
+            "The nonce must be 8 to 64 letters or digits".into(),
+        )));
+    }
+    let now = Utc::now().timestamp() as u64;
+    if now.abs_diff(timestamp) > *SIGNATURE_TTL {
+        return Err(reject::custom(ApiError::ReplayedSignature));
+    }
+
+    let expires_at = Utc
+        .timestamp_opt((timestamp + *SIGNATURE_TTL) as i64, 0)
+        .unwrap();
+    if !db
+        .consume_signature_nonce(&wallet.to_lowercase(), nonce, expires_at)
+        .await
+        .map_err(|e| reject::custom(ApiError::DbError(e)))?
+    {
+        return Err(reject::custom(ApiError::ReplayedSignature));
+    }
+
+    Ok(())
+}
+
+async fn with_signature_nickname(
     credentials: json_requests::SetNickname,
+    db: DB,
 ) -> Result<json_requests::SetNickname, warp::Rejection> {
-    if tools::verify_signature(
-        &credentials.address,
-        &credentials.nickname,
-        &credentials.signature,
-    ) {
+    let msg = format!(
+        "{} {} {}",
+        &credentials.nickname, &credentials.nonce, credentials.timestamp
+    );
+    if tools::verify_signature(&credentials.address, &msg, &credentials.signature) {
+        consume_signature_nonce(
+            &db,
+            &credentials.address,
+            &credentials.nonce,
+            credentials.timestamp,
+        )
+        .await?;
         Ok(credentials)
     } else {
         Err(reject::custom(ApiError::BadSignature(
             credentials.address.to_string(),
-            credentials.nickname.to_string(),
+            msg,
             credentials.signature,
         )))
     }
 }
 
-async fn with_signature_referal<'a>(
+async fn with_signature_referal(
     credentials: json_requests::CreateReferal,
+    db: DB,
 ) -> Result<json_requests::CreateReferal, warp::Rejection> {
-    let msg = format!("{} {}", &credentials.refer_to, &credentials.referal);
+    let msg = format!(
+        "{} {} {} {}",
+        &credentials.refer_to, &credentials.referal, &credentials.nonce, credentials.timestamp
+    );
     if tools::verify_signature(&credentials.referal, &msg, &credentials.signature) {
+        consume_signature_nonce(
+            &db,
+            &credentials.referal,
+            &credentials.nonce,
+            credentials.timestamp,
+        )
+        .await?;
         Ok(credentials)
     } else {
         Err(reject::custom(ApiError::BadSignature(
@@ -81,17 +166,27 @@ async fn with_signature_referal<'a>(
 //     }
 // }
 
-async fn with_signature_connect_wallet<'a>(
+async fn with_signature_connect_wallet(
     credentials: json_requests::ConnectWallet,
+    db: DB,
 ) -> Result<json_requests::ConnectWallet, warp::Rejection> {
     let msg = format!(
-        "CONNECT WALLET {} {} {} {}",
+        "CONNECT WALLET {} {} {} {} {} {}",
         &credentials.partner_wallet,
         &credentials.user_wallet,
         &credentials.site_id,
         &credentials.sub_id,
+        &credentials.nonce,
+        credentials.timestamp,
     );
     if tools::verify_signature(&credentials.user_wallet, &msg, &credentials.signature) {
+        consume_signature_nonce(
+            &db,
+            &credentials.user_wallet,
+            &credentials.nonce,
+            credentials.timestamp,
+        )
+        .await?;
         Ok(credentials)
     } else {
         Err(reject::custom(ApiError::BadSignature(
@@ -102,7 +197,7 @@ async fn with_signature_connect_wallet<'a>(
     }
 }
 
-fn extract_token(headers: &HeaderMap<HeaderValue>) -> Result<(String, Payload), ApiError> {
+fn extract_token(headers: &HeaderMap<HeaderValue>) -> Result<&str, ApiError> {
     let header = match headers.get(AUTHORIZATION) {
         Some(h) => h,
         None => return Err(ApiError::NoAuthError),
@@ -111,47 +206,42 @@ fn extract_token(headers: &HeaderMap<HeaderValue>) -> Result<(String, Payload),
         Ok(h) => h,
         Err(_) => return Err(ApiError::NoAuthError),
     };
-    if !auth_header.starts_with("Bearer ") {
-        return Err(ApiError::InvalidAuthHeaderError);
-    }
-    let token = auth_header.trim_start_matches("Bearer ").to_owned();
-    let parts = token.split('.').collect::<Vec<&str>>();
-    if parts.len() != 3 {
-        return Err(ApiError::MalformedToken);
-    }
-    let decoded = serde_json::from_str::<jwt::Payload>(
-        str::from_utf8(
-            &general_purpose::STANDARD_NO_PAD
-                .decode(parts[1])
-                .map_err(|_| ApiError::MalformedToken)?,
-        )
-        .map_err(|_| ApiError::MalformedToken)?,
-    )
-    .map_err(|_| ApiError::MalformedToken)?;
-    Ok((
-        auth_header.trim_start_matches("Bearer ").to_owned(),
-        decoded,
-    ))
+    auth_header
+        .strip_prefix("Bearer ")
+        .ok_or(ApiError::InvalidAuthHeaderError)
+}
+
+fn verified_payload(
+    headers: &HeaderMap<HeaderValue>,
+    audience: &str,
+) -> Result<Payload, warp::Rejection> {
+    let token = extract_token(headers).map_err(reject::custom)?;
+    let payload = JWT_KEYS
+        .verify_token(token, audience, Utc::now().timestamp() as u64)
+        .map_err(|e| match e {
+            TokenError::Expired => reject::custom(ApiError::TokenExpired),
+            e => reject::custom(ApiError::InvalidToken(e.to_string())),
+        })?;
+    debug!("Token {:?}", payload);
+
+    Ok(payload)
 }
 
 async fn auth_verified(headers: HeaderMap<HeaderValue>, db: DB) -> Result<String, warp::Rejection> {
-    match extract_token(&headers) {
-        Ok((token, decoded)) => {
-            debug!("Token {:?}", decoded);
-            let partner = db
-                .get_partner_by_login(&decoded.sub)
-                .await
-                .map_err(|e| reject::custom(ApiError::DbError(e)))?;
-            let _token_serialized = tools::serialize_token(
-                &token,
-                &format!("{:?}{:?}", *PASSWORD_SALT, partner.password),
-            )
-            .map_err(|_| reject::custom(ApiError::MalformedToken))?;
-
-            Ok(partner.main_wallet)
-        }
-        Err(e) => Err(reject::custom(e)),
+    let payload = verified_payload(&headers, &JWT_PARTNER_AUDIENCE)?;
+
+    // tokens issued before the last password change are revoked
+    let token_version = db
+        .get_partner_token_version(&payload.sub)
+        .await
+        .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+    if token_version != Some(payload.ver) {
+        return Err(reject::custom(ApiError::InvalidToken(
+            "the session was revoked".into(),
+        )));
     }
+
+    Ok(payload.sub)
 }
 
 fn with_auth(db: DB) -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
@@ -161,6 +251,82 @@ fn with_auth(db: DB) -> impl Filter<Extract = (String,), Error = warp::Rejection
         .and_then(auth_verified)
 }
 
+async fn player_auth_verified(headers: HeaderMap<HeaderValue>) -> Result<String, warp::Rejection> {
+    verified_payload(&headers, &JWT_PLAYER_AUDIENCE).map(|payload| payload.sub)
+}
+
+/// Wallet of the player session opened with sign-in with ethereum
+fn with_player_auth() -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+    headers_cloned().and_then(player_auth_verified)
+}
+
+/// Wallet which signed the sign-in with ethereum message, the nonce of the message is used up
+async fn with_siwe(
+    credentials: json_requests::SiweLogin,
+    db: DB,
+) -> Result<String, warp::Rejection> {
+    let message: SiweMessage = credentials
+        .message
+        .parse()
+        .map_err(|e| reject::custom(ApiError::BadSiweMessage(e)))?;
+    message
+        .validate(&SIWE_DOMAIN, &SIWE_ORIGIN, Utc::now())
+        .map_err(|e| reject::custom(ApiError::BadSiweMessage(e)))?;
+    let known_chain = match i64::try_from(message.chain_id) {
+        Ok(chain_id) => db
+            .query_network(chain_id)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?
+            .is_some(),
+        Err(_) => false,
+    };
+    if !known_chain {
+        return Err(reject::custom(ApiError::BadSiweMessage(SiweError::Chain(
+            message.chain_id,
+        ))));
+    }
+
+    let wallet = message.address.to_lowercase();
+    if !tools::verify_signature(
+        &wallet,
+        &credentials.message,
+        credentials.signature.trim_start_matches("0x"),
+    ) {
+        return Err(reject::custom(ApiError::BadSignature(
+            wallet,
+            credentials.message,
+            credentials.signature,
+        )));
+    }
+    if !db
+        .consume_siwe_nonce(&message.nonce)
+        .await
+        .map_err(|e| reject::custom(ApiError::DbError(e)))?
+    {
+        return Err(reject::custom(ApiError::BadNonce));
+    }
+
+    Ok(wallet)
+}
+
+async fn admin_verified(headers: HeaderMap<HeaderValue>) -> Result<(), warp::Rejection> {
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .ok_or_else(|| reject::custom(ApiError::NotAdmin))?;
+    // the hashes are compared so the comparison time doesn't leak the token
+    if ADMIN_TOKEN.is_empty() || tools::blake_hash(token) != tools::blake_hash(&ADMIN_TOKEN) {
+        return Err(reject::custom(ApiError::NotAdmin));
+    }
+
+    Ok(())
+}
+
+fn with_admin() -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    headers_cloned().and_then(admin_verified).untuple_one()
+}
+
 // async fn with_auth_partner<'a>(
 //     signature: String,
 //     timestamp: u64,
@@ -213,6 +379,21 @@ fn json_body_login_partner(
     warp::body::content_length_limit(1024 * 16).and(warp::body::json())
 }
 
+fn json_body_siwe_login(
+) -> impl Filter<Extract = (json_requests::SiweLogin,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+}
+
+fn json_body_session_nickname(
+) -> impl Filter<Extract = (json_requests::SessionNickname,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+}
+
+fn json_body_refresh_token(
+) -> impl Filter<Extract = (json_requests::RefreshTokenRequest,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+}
+
 fn json_body_add_partner_contacts(
 ) -> impl Filter<Extract = (json_requests::AddPartnerContacts,), Error = warp::Rejection> + Clone {
     warp::body::content_length_limit(1024 * 16).and(warp::body::json())
@@ -260,6 +441,31 @@ fn json_body_submit_question(
     warp::body::content_length_limit(1024 * 16).and(warp::body::json())
 }
 
+fn json_body_network_info(
+) -> impl Filter<Extract = (db_models::NetworkInfo,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+}
+
+fn json_body_network_url(
+) -> impl Filter<Extract = (json_requests::NetworkUrl,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+}
+
+fn json_body_new_token(
+) -> impl Filter<Extract = (json_requests::NewToken,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+}
+
+fn json_body_new_game(
+) -> impl Filter<Extract = (json_requests::NewGame,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+}
+
+fn json_body_game_abi(
+) -> impl Filter<Extract = (db_models::GameAbi,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+}
+
 // NETWORKS
 pub fn get_networks(
     db: DB,
@@ -270,10 +476,22 @@ pub fn get_networks(
         .and_then(handlers::get_networks)
 }
 
+pub fn get_network_sync(
+    db: DB,
+    rpc_health: RpcHealthRegistry,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!(i64 / "sync")
+        .and(warp::get())
+        .and(with_db(db))
+        .and(with_rpc_health(rpc_health))
+        .and_then(handlers::get_network_sync)
+}
+
 pub fn network(
     db: DB,
+    rpc_health: RpcHealthRegistry,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
-    warp::path("network").and(get_networks(db))
+    warp::path("network").and(get_networks(db.clone()).or(get_network_sync(db, rpc_health)))
 }
 
 // RPCS
@@ -286,8 +504,20 @@ pub fn get_rpcs(
         .and_then(handlers::get_rpcs)
 }
 
-pub fn rpc(db: DB) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
-    warp::path("rpc").and(get_rpcs(db))
+pub fn get_rpcs_health(
+    rpc_health: RpcHealthRegistry,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("health" / i64)
+        .and(warp::get())
+        .and(with_rpc_health(rpc_health))
+        .and_then(handlers::get_rpcs_health)
+}
+
+pub fn rpc(
+    db: DB,
+    rpc_health: RpcHealthRegistry,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("rpc").and(get_rpcs(db).or(get_rpcs_health(rpc_health)))
 }
 
 // EXPLORERS
@@ -323,6 +553,14 @@ pub fn get_token_price(
         .and(with_db(db))
         .and_then(handlers::get_token_price)
 }
+pub fn get_token_price_history(
+    db: DB,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("price" / "history" / String / u64 / u64)
+        .and(warp::get())
+        .and(with_db(db))
+        .and_then(handlers::get_token_price_history)
+}
 pub fn get_tokens(
     db: DB,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
@@ -335,7 +573,11 @@ pub fn get_tokens(
 pub fn token(
     db: DB,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
-    warp::path("token").and(get_tokens(db.clone()).or(get_token_price(db)))
+    warp::path("token").and(
+        get_tokens(db.clone())
+            .or(get_token_price(db.clone()))
+            .or(get_token_price_history(db)),
+    )
 }
 
 // GAMES
@@ -376,11 +618,23 @@ pub fn set_nickname(
     warp::path!("set")
         .and(warp::post())
         .and(json_body_set_nickname())
+        .and(with_db(db.clone()))
         .and_then(with_signature_nickname)
         .and(with_db(db))
         .and_then(handlers::set_nickname)
 }
 
+pub fn set_session_nickname(
+    db: DB,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("session")
+        .and(warp::post())
+        .and(with_player_auth())
+        .and(json_body_session_nickname())
+        .and(with_db(db))
+        .and_then(handlers::set_session_nickname)
+}
+
 pub fn get_player(
     db: DB,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
@@ -411,6 +665,7 @@ pub fn create_referal(
     warp::path!("subscribe")
         .and(warp::post())
         .and(json_body_subscribe_referal())
+        .and(with_db(db.clone()))
         .and_then(with_signature_referal)
         .and(with_db(db))
         .and_then(handlers::player::create_referal)
@@ -421,7 +676,11 @@ pub fn player(
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     warp::path("player").and(
         get_player(db.clone())
-            .or(warp::path("nickname").and(get_nickname(db.clone()).or(set_nickname(db.clone()))))
+            .or(warp::path("nickname").and(
+                get_nickname(db.clone())
+                    .or(set_nickname(db.clone()))
+                    .or(set_session_nickname(db.clone())),
+            ))
             .or(get_latest_games(db.clone()))
             .or(get_player_totals(db.clone()))
             .or(warp::path("referal").and(create_referal(db))),
@@ -543,6 +802,28 @@ pub fn login_partner(
         .and_then(handlers::login_partner)
 }
 
+pub fn login_partner_wallet(
+    db: DB,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("login" / "wallet")
+        .and(warp::post())
+        .and(json_body_siwe_login())
+        .and(with_db(db.clone()))
+        .and_then(with_siwe)
+        .and(with_db(db))
+        .and_then(handlers::login_partner_wallet)
+}
+
+pub fn refresh_partner_token(
+    db: DB,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("token" / "refresh")
+        .and(warp::post())
+        .and(json_body_refresh_token())
+        .and(with_db(db))
+        .and_then(handlers::refresh_partner_token)
+}
+
 pub fn get_partner(
     db: DB,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
@@ -672,6 +953,7 @@ pub fn connect_wallet_subid(
     warp::path!("connect")
         .and(warp::post())
         .and(json_body_connect_wallet())
+        .and(with_db(db.clone()))
         .and_then(with_signature_connect_wallet)
         .and(with_db(db))
         .and_then(handlers::connect_wallet)
@@ -831,6 +1113,8 @@ pub fn partners(
             .or(submit_partner_withdraw_request(db.clone()))
             .or(get_conected_totals(db.clone()))
             .or(login_partner(db.clone()))
+            .or(login_partner_wallet(db.clone()))
+            .or(refresh_partner_token(db.clone()))
             .or(get_partner_connected_wallets_info(db.clone()))
             .or(get_partner_connected_wallets(db.clone()))
             .or(get_partner_connected_wallets_exact_date(db.clone()))
@@ -860,6 +1144,194 @@ pub fn partners(
     )
 }
 
+// AUTH
+pub fn get_siwe_nonce(
+    db: DB,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("nonce")
+        .and(warp::get())
+        .and(with_db(db))
+        .and_then(handlers::get_siwe_nonce)
+}
+
+pub fn login_player(
+    db: DB,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("login")
+        .and(warp::post())
+        .and(json_body_siwe_login())
+        .and(with_db(db))
+        .and_then(with_siwe)
+        .and_then(handlers::login_player)
+}
+
+pub fn auth(db: DB) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("auth").and(get_siwe_nonce(db.clone()).or(login_player(db)))
+}
+
+// ADMIN
+pub fn admin_network(
+    db: DB,
+    registry: RegistrySender,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let add = warp::path!("add")
+        .and(json_body_network_info())
+        .and(with_db(db.clone()))
+        .and(with_registry(registry.clone()))
+        .and_then(handlers::add_network);
+    let update = warp::path!("update")
+        .and(json_body_network_info())
+        .and(with_db(db.clone()))
+        .and(with_registry(registry.clone()))
+        .and_then(handlers::update_network);
+    let delete = warp::path!("delete" / i64)
+        .and(with_db(db))
+        .and(with_registry(registry))
+        .and_then(handlers::delete_network);
+    warp::path("network").and(add.or(update).or(delete))
+}
+
+pub fn admin_rpc(
+    db: DB,
+    registry: RegistrySender,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let add = warp::path!("add")
+        .and(json_body_network_url())
+        .and(with_db(db.clone()))
+        .and(with_registry(registry.clone()))
+        .and_then(handlers::add_rpc);
+    let update = warp::path!("update" / i64)
+        .and(json_body_network_url())
+        .and(with_db(db.clone()))
+        .and(with_registry(registry.clone()))
+        .and_then(handlers::update_rpc);
+    let delete = warp::path!("delete" / i64)
+        .and(with_db(db))
+        .and(with_registry(registry))
+        .and_then(handlers::delete_rpc);
+    warp::path("rpc").and(add.or(update).or(delete))
+}
+
+pub fn admin_block_explorer(
+    db: DB,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let add = warp::path!("add")
+        .and(json_body_network_url())
+        .and(with_db(db.clone()))
+        .and_then(handlers::add_block_explorer);
+    let update = warp::path!("update" / i64)
+        .and(json_body_network_url())
+        .and(with_db(db.clone()))
+        .and_then(handlers::update_block_explorer);
+    let delete = warp::path!("delete" / i64)
+        .and(with_db(db))
+        .and_then(handlers::delete_block_explorer);
+    warp::path("block_explorer").and(add.or(update).or(delete))
+}
+
+pub fn admin_token(
+    db: DB,
+    registry: RegistrySender,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let add = warp::path!("add")
+        .and(json_body_new_token())
+        .and(with_db(db.clone()))
+        .and(with_registry(registry.clone()))
+        .and_then(handlers::add_token);
+    let update = warp::path!("update" / i64)
+        .and(json_body_new_token())
+        .and(with_db(db.clone()))
+        .and(with_registry(registry.clone()))
+        .and_then(handlers::update_token);
+    let delete = warp::path!("delete" / i64)
+        .and(with_db(db))
+        .and(with_registry(registry))
+        .and_then(handlers::delete_token);
+    warp::path("token").and(add.or(update).or(delete))
+}
+
+pub fn admin_game(
+    db: DB,
+    registry: RegistrySender,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let add = warp::path!("add")
+        .and(json_body_new_game())
+        .and(with_db(db.clone()))
+        .and(with_registry(registry.clone()))
+        .and_then(handlers::add_game);
+    let update = warp::path!("update" / i64)
+        .and(json_body_new_game())
+        .and(with_db(db.clone()))
+        .and(with_registry(registry.clone()))
+        .and_then(handlers::update_game);
+    let delete = warp::path!("delete" / i64)
+        .and(with_db(db))
+        .and(with_registry(registry))
+        .and_then(handlers::delete_game);
+    warp::path("game").and(add.or(update).or(delete))
+}
+
+pub fn admin_abi(
+    db: DB,
+    registry: RegistrySender,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let add = warp::path!("add")
+        .and(json_body_game_abi())
+        .and(with_db(db.clone()))
+        .and_then(handlers::add_game_abi);
+    let update = warp::path!("update")
+        .and(json_body_game_abi())
+        .and(with_db(db.clone()))
+        .and(with_registry(registry))
+        .and_then(handlers::update_game_abi);
+    let delete = warp::path!("delete" / String)
+        .and(with_db(db))
+        .and_then(handlers::delete_game_abi);
+    warp::path("abi").and(add.or(update).or(delete))
+}
+
+pub fn admin_dead_letter(
+    db: DB,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let list = warp::path!("list" / i64 / ..)
+        .and(
+            warp::path::param::<i64>()
+                .map(Some)
+                .or_else(|_| async { Ok::<(Option<i64>,), std::convert::Infallible>((None,)) }),
+        )
+        .and(warp::path::end())
+        .and(with_db(db.clone()))
+        .and_then(handlers::get_dead_letters);
+    let reprocess = warp::path!("reprocess" / i64)
+        .and(with_db(db))
+        .and_then(handlers::reprocess_dead_letters);
+    warp::path("dead_letter").and(list.or(reprocess))
+}
+
+pub fn admin_bet(
+    db: DB,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("bet" / "recompute_multipliers")
+        .and(with_db(db))
+        .and_then(handlers::recompute_multipliers)
+}
+
+pub fn admin(
+    db: DB,
+    registry: RegistrySender,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("admin").and(warp::post()).and(with_admin()).and(
+        admin_network(db.clone(), registry.clone())
+            .or(admin_rpc(db.clone(), registry.clone()))
+            .or(admin_block_explorer(db.clone()))
+            .or(admin_token(db.clone(), registry.clone()))
+            .or(admin_game(db.clone(), registry.clone()))
+            .or(admin_abi(db.clone(), registry))
+            .or(admin_dead_letter(db.clone()))
+            .or(admin_bet(db)),
+    )
+}
+
 // GENERAL
 pub fn get_totals(
     db: DB,
@@ -869,6 +1341,15 @@ pub fn get_totals(
         .and_then(handlers::get_totals)
 }
 
+pub fn get_db_queue(
+    db_queue: SharedDbQueueMetrics,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("db_queue")
+        .and(warp::get())
+        .and(with_db_queue(db_queue))
+        .and_then(handlers::get_db_queue)
+}
+
 pub fn get_leaderboard(
     db: DB,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
@@ -889,11 +1370,13 @@ pub fn submit_error(
 
 pub fn general(
     db: DB,
+    db_queue: SharedDbQueueMetrics,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     warp::path("general").and(
         get_totals(db.clone())
             .or(submit_error(db.clone()))
-            .or(get_leaderboard(db)),
+            .or(get_leaderboard(db))
+            .or(get_db_queue(db_queue)),
     )
 }
 
@@ -908,22 +1391,90 @@ pub fn general(
 pub fn init_filters(
     db: DB,
     bet_sender: WsDataFeedSender,
+    rpc_health: RpcHealthRegistry,
+    registry: RegistrySender,
+    shutdown: ShutdownReceiver,
+    db_queue: SharedDbQueueMetrics,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
-    network(db.clone())
-        .or(rpc(db.clone()))
+    network(db.clone(), rpc_health.clone())
+        .or(rpc(db.clone(), rpc_health))
         .or(block_explorer(db.clone()))
         .or(token(db.clone()))
         .or(game(db.clone()))
         .or(player(db.clone()))
         .or(abi(db.clone()))
         .or(bets(db.clone()))
-        .or(general(db.clone()))
+        .or(general(db.clone(), db_queue))
         .or(partners(db.clone()))
+        .or(auth(db.clone()))
+        .or(admin(db.clone(), registry))
         .or(warp::path!("updates")
             .and(warp::ws())
             .and(with_db(db))
             .and(with_channel(bet_sender))
-            .map(|ws: warp::ws::Ws, db, ch| {
-                ws.on_upgrade(move |socket| handlers::websockets_handler(socket, db, ch))
+            .and(with_shutdown(shutdown))
+            .map(|ws: warp::ws::Ws, db, ch, shutdown| {
+                ws.on_upgrade(move |socket| handlers::websockets_handler(socket, db, ch, shutdown))
             }))
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::config::DatabaseSettings;
+    use web3::signing::{Key, SecretKey, SecretKeyRef};
+
+    #[tokio::test]
+    async fn resplit_signature_test() {
+        let key: SecretKey = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+            .parse()
+            .unwrap();
+        let key = SecretKeyRef::new(&key);
+        let signature = key
+            .sign_message(&tools::hash_message("a b c1234567 1700000000"))
+            .unwrap();
+        let signature = hex::encode(
+            [
+                &signature.r.0[..],
+                &signature.s.0[..],
+                &[signature.v as u8 + 27],
+            ]
+            .concat(),
+        );
+
+        // signed for the nickname `a b` with the nonce `c1234567`
+        let resplit = json_requests::SetNickname {
+            address: format!("{:?}", key.address()),
+            nickname: "a".into(),
+            nonce: "b c1234567".into(),
+            timestamp: 1700000000,
+            signature,
+        };
+        // the re-split fields give the same message, only the nonce format tells them apart
+        let message = format!(
+            "{} {} {}",
+            resplit.nickname, resplit.nonce, resplit.timestamp
+        );
+        assert!(tools::verify_signature(
+            &resplit.address,
+            &message,
+            &resplit.signature
+        ));
+
+        let db = DB::new(&DatabaseSettings {
+            username: String::new(),
+            password: String::new(),
+            host: "localhost".into(),
+            port: 5432,
+            database_name: String::new(),
+        })
+        .await;
+        let Err(rejection) = with_signature_nickname(resplit, db).await else {
+            panic!("The resplit message was accepted");
+        };
+        assert!(matches!(
+            rejection.find::<ApiError>(),
+            Some(ApiError::ArbitraryError(_))
+        ));
+    }
+}
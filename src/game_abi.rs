@@ -0,0 +1,432 @@
+use crate::models::db_models::{GameAbi, GameInfo};
+use ethabi::param_type::Reader;
+use ethabi::{Event, ParamType, RawLog, Token};
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+use web3::signing::keccak256;
+use web3::types::{Log, H160, H256};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum GameAbiError {
+    #[error("Malformed address `{0}`")]
+    BadAddress(String),
+
+    #[error("Malformed event signature `{0}`")]
+    BadSignature(String),
+
+    #[error("Malformed event types `{0}`: {1}")]
+    BadTypes(String, String),
+
+    #[error("There are {0} event names for {1} event types")]
+    NamesMismatch(usize, usize),
+
+    #[error("Empty or duplicated event name `{0}`")]
+    BadName(String),
+
+    #[error("Malformed event declaration `{0}`")]
+    BadDeclaration(String),
+
+    #[error("The signature of `{0}` is `{1:?}`, not `{2}`")]
+    SignatureMismatch(String, H256, String),
+
+    #[error("The event types aren't among the parameters of `{0}`")]
+    TypesMismatch(String),
+
+    #[error("Malformed abi of the event `{0}`: {1}")]
+    BadAbi(String, String),
+
+    #[error("The event `{0}` has no `player` address parameter")]
+    NoPlayer(String),
+
+    #[error("Multiplier precision `{0}` isn't positive")]
+    BadMultiplierPrecision(i64),
+}
+
+/// Decoder of the game's result event
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventDecoder {
+    /// Solidity JSON abi of the event, both indexed and non indexed parameters are decoded
+    Abi(Event),
+    /// Legacy layout of the non indexed parameters, the player is the first indexed one
+    Layout {
+        types: Vec<ParamType>,
+        names: Vec<String>,
+    },
+}
+
+impl EventDecoder {
+    /// Decodes the parameters of the event by their names
+    pub fn decode(&self, log: &Log) -> Result<HashMap<String, Token>, ethabi::Error> {
+        match self {
+            EventDecoder::Abi(event) => Ok(event
+                .parse_log(RawLog {
+                    topics: log.topics.clone(),
+                    data: log.data.0.clone(),
+                })?
+                .params
+                .into_iter()
+                .map(|param| (param.name, param.value))
+                .collect()),
+            EventDecoder::Layout { types, names } => {
+                let mut decoded: HashMap<String, Token> = names
+                    .iter()
+                    .cloned()
+                    .zip(ethabi::decode(types, &log.data.0)?)
+                    .collect();
+                if let Some(player) = log.topics.get(1) {
+                    decoded
+                        .entry("player".to_string())
+                        .or_insert_with(|| Token::Address(H160::from_slice(&player.0[12..])));
+                }
+                Ok(decoded)
+            }
+        }
+    }
+}
+
+/// Checked layout of the game's result event
+#[derive(Debug)]
+pub struct EventLayout {
+    pub signature: H256,
+    pub decoder: EventDecoder,
+}
+
+fn decode_hex<const N: usize>(value: &str) -> Option<[u8; N]> {
+    let mut decoded = [0; N];
+    hex::decode_to_slice(value.strip_prefix("0x")?, &mut decoded).ok()?;
+    Some(decoded)
+}
+
+pub fn parse_address(address: &str) -> Result<H160, GameAbiError> {
+    decode_hex::<20>(address)
+        .map(H160::from)
+        .ok_or_else(|| GameAbiError::BadAddress(address.to_string()))
+}
+
+pub fn parse_signature(signature: &str) -> Result<H256, GameAbiError> {
+    decode_hex::<32>(signature)
+        .map(H256::from)
+        .ok_or_else(|| GameAbiError::BadSignature(signature.to_string()))
+}
+
+/// Units of the event's `multiplier` field per 1.0x
+pub fn parse_multiplier_precision(precision: i64) -> Result<f64, GameAbiError> {
+    if precision <= 0 {
+        return Err(GameAbiError::BadMultiplierPrecision(precision));
+    }
+    Ok(precision as f64)
+}
+
+/// Splits the parameters of the declaration like `Name(uint256,(address,bool)[])`
+fn declared_params(declaration: &str) -> Option<Vec<&str>> {
+    let params = declaration
+        .split_once('(')
+        .filter(|(name, _)| !name.is_empty())?
+        .1
+        .strip_suffix(')')?;
+    if params.is_empty() {
+        return Some(Vec::new());
+    }
+
+    let mut split = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (index, symbol) in params.char_indices() {
+        match symbol {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1)?,
+            ',' if depth == 0 => {
+                split.push(&params[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    split.push(&params[start..]);
+    Some(split)
+}
+
+fn check_names<'a>(names: impl Iterator<Item = &'a str>) -> Result<(), GameAbiError> {
+    let mut seen = HashSet::new();
+    for name in names {
+        if name.is_empty() || !seen.insert(name) {
+            return Err(GameAbiError::BadName(name.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Checks the Solidity JSON abi of the event, it has to hash to the signature
+/// and name the player's address `player`
+fn validate_abi_event(signature: H256, abi: &str) -> Result<EventDecoder, GameAbiError> {
+    let event = serde_json::from_str::<Event>(abi)
+        .map_err(|e| GameAbiError::BadAbi(abi.to_string(), e.to_string()))?;
+    if event.anonymous {
+        return Err(GameAbiError::BadAbi(
+            event.name,
+            "anonymous events have no signature".to_string(),
+        ));
+    }
+    if event.signature() != signature {
+        return Err(GameAbiError::SignatureMismatch(
+            event.name.clone(),
+            event.signature(),
+            format!("{:?}", signature),
+        ));
+    }
+    check_names(event.inputs.iter().map(|input| input.name.as_str()))?;
+    if !event
+        .inputs
+        .iter()
+        .any(|input| input.name == "player" && input.kind == ParamType::Address)
+    {
+        return Err(GameAbiError::NoPlayer(event.name));
+    }
+
+    Ok(EventDecoder::Abi(event))
+}
+
+/// Checks the legacy layout of the event, the names have to match the types one to one,
+/// the declaration, if any, has to hash to the signature
+/// and contain the types among its parameters in the same order
+fn validate_layout(
+    signature: H256,
+    types: &str,
+    names: &str,
+    declaration: Option<&str>,
+) -> Result<EventDecoder, GameAbiError> {
+    let param_types = serde_json::from_str::<Vec<ParamType>>(types)
+        .map_err(|e| GameAbiError::BadTypes(types.to_string(), e.to_string()))?;
+
+    let param_names: Vec<String> = names.split(' ').map(|name| name.to_string()).collect();
+    if param_names.len() != param_types.len() {
+        return Err(GameAbiError::NamesMismatch(
+            param_names.len(),
+            param_types.len(),
+        ));
+    }
+    check_names(param_names.iter().map(|name| name.as_str()))?;
+
+    if let Some(declaration) = declaration {
+        let hash = H256::from(keccak256(declaration.as_bytes()));
+        if hash != signature {
+            return Err(GameAbiError::SignatureMismatch(
+                declaration.to_string(),
+                hash,
+                format!("{:?}", signature),
+            ));
+        }
+
+        let declared = declared_params(declaration)
+            .ok_or_else(|| GameAbiError::BadDeclaration(declaration.to_string()))?
+            .into_iter()
+            .map(Reader::read)
+            .collect::<Result<Vec<ParamType>, _>>()
+            .map_err(|_| GameAbiError::BadDeclaration(declaration.to_string()))?;
+        // the indexed parameters are missing from the types
+        let mut declared = declared.iter();
+        if !param_types
+            .iter()
+            .all(|param| declared.any(|candidate| candidate == param))
+        {
+            return Err(GameAbiError::TypesMismatch(declaration.to_string()));
+        }
+    }
+
+    Ok(EventDecoder::Layout {
+        types: param_types,
+        names: param_names,
+    })
+}
+
+/// Checks the event of the game, the abi takes precedence over the legacy layout
+pub fn validate_event(
+    signature: &str,
+    types: &str,
+    names: &str,
+    declaration: Option<&str>,
+    abi: Option<&str>,
+) -> Result<EventLayout, GameAbiError> {
+    let signature_hash = parse_signature(signature)?;
+    let decoder = match abi {
+        Some(abi) => validate_abi_event(signature_hash, abi)?,
+        None => validate_layout(signature_hash, types, names, declaration)?,
+    };
+
+    Ok(EventLayout {
+        signature: signature_hash,
+        decoder,
+    })
+}
+
+pub fn validate_abi(abi: &GameAbi) -> Result<EventLayout, GameAbiError> {
+    validate_event(
+        &abi.signature,
+        &abi.types,
+        &abi.names,
+        abi.event.as_deref(),
+        abi.abi.as_deref(),
+    )
+}
+
+/// Checks the game along with its event, returns the contract address and the event layout
+pub fn validate_game(game: &GameInfo) -> Result<(H160, EventLayout), GameAbiError> {
+    let address = parse_address(&game.address)?;
+    parse_multiplier_precision(game.multiplier_precision)?;
+    let layout = validate_event(
+        &game.event_signature,
+        &game.event_types,
+        &game.event_names,
+        game.event.as_deref(),
+        game.event_abi.as_deref(),
+    )?;
+    Ok((address, layout))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    const TRANSFER: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+    const TRANSFER_ABI: &str = r#"{
+        "type": "event",
+        "name": "Transfer",
+        "anonymous": false,
+        "inputs": [
+            {"name": "player", "type": "address", "indexed": true},
+            {"name": "to", "type": "address", "indexed": true},
+            {"name": "value", "type": "uint256", "indexed": false}
+        ]
+    }"#;
+
+    fn transfer_log() -> Log {
+        Log {
+            topics: vec![
+                parse_signature(TRANSFER).unwrap(),
+                H256::from(H160::from_low_u64_be(1)),
+                H256::from(H160::from_low_u64_be(2)),
+            ],
+            data: web3::types::Bytes(ethabi::encode(&[Token::Uint(7.into())])),
+            address: H160::zero(),
+            block_hash: None,
+            block_number: None,
+            transaction_hash: None,
+            transaction_index: None,
+            log_index: None,
+            transaction_log_index: None,
+            log_type: None,
+            removed: None,
+        }
+    }
+
+    #[test]
+    fn validate_event_test() {
+        let layout = validate_event(
+            TRANSFER,
+            r#"["uint256"]"#,
+            "value",
+            Some("Transfer(address,address,uint256)"),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            layout.decoder,
+            EventDecoder::Layout {
+                types: vec![ParamType::Uint(256)],
+                names: vec!["value".to_string()],
+            }
+        );
+
+        assert_eq!(
+            validate_event(TRANSFER, r#"["uint256","address"]"#, "value", None, None).unwrap_err(),
+            GameAbiError::NamesMismatch(1, 2)
+        );
+        assert!(matches!(
+            validate_event(
+                TRANSFER,
+                r#"["uint256"]"#,
+                "value",
+                Some("Transfer(uint256)"),
+                None
+            ),
+            Err(GameAbiError::SignatureMismatch(..))
+        ));
+        assert_eq!(
+            validate_event(
+                TRANSFER,
+                r#"["bool"]"#,
+                "value",
+                Some("Transfer(address,address,uint256)"),
+                None
+            )
+            .unwrap_err(),
+            GameAbiError::TypesMismatch("Transfer(address,address,uint256)".to_string())
+        );
+        assert!(matches!(
+            validate_event("0x1234", r#"["uint256"]"#, "value", None, None),
+            Err(GameAbiError::BadSignature(_))
+        ));
+    }
+
+    #[test]
+    fn validate_abi_event_test() {
+        let layout = validate_event(TRANSFER, "", "", None, Some(TRANSFER_ABI)).unwrap();
+        assert!(matches!(layout.decoder, EventDecoder::Abi(_)));
+
+        let approval = TRANSFER_ABI.replace("Transfer", "Approval");
+        assert!(matches!(
+            validate_event(TRANSFER, "", "", None, Some(&approval)),
+            Err(GameAbiError::SignatureMismatch(..))
+        ));
+        assert!(matches!(
+            validate_event(TRANSFER, "", "", None, Some("[]")),
+            Err(GameAbiError::BadAbi(..))
+        ));
+        let from = TRANSFER_ABI.replace(r#""player""#, r#""from""#);
+        assert_eq!(
+            validate_event(TRANSFER, "", "", None, Some(&from)).unwrap_err(),
+            GameAbiError::NoPlayer("Transfer".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_multiplier_precision_test() {
+        assert_eq!(parse_multiplier_precision(10000).unwrap(), 10000.0);
+        assert!(matches!(
+            parse_multiplier_precision(0),
+            Err(GameAbiError::BadMultiplierPrecision(0))
+        ));
+    }
+
+    #[test]
+    fn decode_test() {
+        let abi = validate_event(TRANSFER, "", "", None, Some(TRANSFER_ABI))
+            .unwrap()
+            .decoder
+            .decode(&transfer_log())
+            .unwrap();
+        assert_eq!(abi["player"], Token::Address(H160::from_low_u64_be(1)));
+        assert_eq!(abi["to"], Token::Address(H160::from_low_u64_be(2)));
+        assert_eq!(abi["value"], Token::Uint(7.into()));
+
+        let layout = EventDecoder::Layout {
+            types: vec![ParamType::Uint(256)],
+            names: vec!["value".to_string()],
+        }
+        .decode(&transfer_log())
+        .unwrap();
+        assert_eq!(layout["player"], Token::Address(H160::from_low_u64_be(1)));
+        assert_eq!(layout["value"], Token::Uint(7.into()));
+    }
+
+    #[test]
+    fn declared_params_test() {
+        assert_eq!(
+            declared_params("Event(uint256,(address,bool)[],uint8[10])"),
+            Some(vec!["uint256", "(address,bool)[]", "uint8[10]"])
+        );
+        assert_eq!(declared_params("Event()"), Some(vec![]));
+        assert_eq!(declared_params("(uint256)"), None);
+        assert_eq!(declared_params("Event(uint256))"), None);
+    }
+}
@@ -2,7 +2,9 @@ use std::collections::HashSet;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
-use crate::communication::WsDataFeedReceiver;
+use crate::communication::{
+    shutdown_requested, SharedDbQueueMetrics, ShutdownReceiver, WsDataFeedReceiver,
+};
 use crate::config;
 use crate::db::DB;
 use crate::errors::ApiError;
@@ -15,14 +17,19 @@ use crate::models::json_requests::{self, WebsocketsIncommingMessage};
 #[allow(unused_imports)]
 use crate::models::json_requests::{
     AddPartnerContacts, AddPartnerSite, AddPartnerSubid, ChangePasswordRequest, ConnectWallet,
-    DeletePartnerContacts, Login, RegisterPartner, SetNickname, SubmitError, SubmitQuestion,
+    DeletePartnerContacts, Login, RegisterPartner, SessionNickname, SetNickname, SiweLogin,
+    SubmitError, SubmitQuestion,
 };
 #[allow(unused_imports)]
 use crate::models::json_responses::{
-    AccessToken, Bets, BlockExplorers, ErrorText, InfoText, JsonResponse, NetworkFullInfo,
-    Networks, ResponseBody, Rpcs, Status, TokenPrice, Tokens,
+    AccessToken, Bets, BlockExplorers, DbQueue, ErrorText, InfoText, JsonResponse, NetworkFullInfo,
+    NetworkSync, Networks, PlayerSession, ResponseBody, Rpcs, RpcsHealth, SiweNonce, Status,
+    TokenPrice, TokenPriceHistory, Tokens,
 };
+use crate::rpc_pool::RpcHealthRegistry;
 pub use abi::*;
+pub use admin::*;
+pub use auth::*;
 pub use bets::*;
 pub use block_explorers::*;
 use futures::stream::SplitStream;
@@ -38,7 +45,7 @@ use serde::Serialize;
 pub use token::*;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
 use tokio::time::{sleep, Duration};
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 use warp::http::StatusCode;
 use warp::ws::{Message, WebSocket};
 use warp::Reply;
@@ -112,6 +119,66 @@ pub mod network {
             networks: networks_full_info,
         })))
     }
+
+    /// Get sync status of the network
+    ///
+    /// Gets the head block, the latest indexed block and the lag between them for a chosen network
+    #[utoipa::path(
+        tag="network",
+        get,
+        path = "/api/network/{network_id}/sync",
+        responses(
+            (status = 200, description = "Network sync status", body = NetworkSync),
+            (status = 404, description = "Network not found", body = ErrorText),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+        params(
+            ("network_id" = i64, Path, description = "Chain ID of the network")
+        ),
+    )]
+    pub async fn get_network_sync(
+        network_id: i64,
+        db: DB,
+        rpc_health: RpcHealthRegistry,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        if db
+            .query_network(network_id)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?
+            .is_none()
+        {
+            return Err(reject::custom(ApiError::NotFound(
+                "network",
+                network_id.to_string(),
+            )));
+        }
+        let last_block = db
+            .query_last_block(network_id)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+
+        let head_block = rpc_health
+            .read()
+            .await
+            .get(&network_id)
+            .and_then(|rpcs| rpcs.iter().filter_map(|rpc| rpc.block_number).max());
+        // the cursor points to the next block to scan
+        let indexed_block = last_block
+            .as_ref()
+            .map(|block| (block.id as u64).saturating_sub(1));
+
+        Ok(gen_arbitrary_response(ResponseBody::NetworkSync(
+            NetworkSync {
+                network_id,
+                head_block,
+                indexed_block,
+                lag: head_block
+                    .zip(indexed_block)
+                    .map(|(head, indexed)| head.saturating_sub(indexed)),
+                indexed_at: last_block.map(|block| block.updated_at),
+            },
+        )))
+    }
 }
 
 pub mod rpcs {
@@ -139,6 +206,37 @@ pub mod rpcs {
 
         Ok(gen_arbitrary_response(ResponseBody::Rpcs(Rpcs { rpcs })))
     }
+
+    /// Get health of the rpcs for the network
+    ///
+    /// Gets latency, error rate and block lag of the rpcs used by the backend for a chosen network
+    #[utoipa::path(
+        tag="rpcs",
+        get,
+        path = "/api/rpc/health/{network_id}",
+        responses(
+            (status = 200, description = "Rpcs health", body = RpcsHealth),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+        params(
+            ("network_id" = i64, Path, description = "Chain ID of the network")
+        ),
+    )]
+    pub async fn get_rpcs_health(
+        network_id: i64,
+        rpc_health: RpcHealthRegistry,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        let rpcs = rpc_health
+            .read()
+            .await
+            .get(&network_id)
+            .cloned()
+            .unwrap_or_default();
+
+        Ok(gen_arbitrary_response(ResponseBody::RpcsHealth(
+            RpcsHealth { rpcs },
+        )))
+    }
 }
 
 pub mod block_explorers {
@@ -186,6 +284,11 @@ pub mod block_explorers {
 
 pub mod token {
     use super::*;
+    use chrono::{TimeZone, Utc};
+
+    /// Max amount of the prices returned by the price history
+    const PRICE_HISTORY_LIMIT: i64 = 1000;
+
     /// Get list of tokens for the network
     ///
     /// Gets a list of tokens for a chosen network
@@ -231,11 +334,64 @@ pub mod token {
         let price = db
             .query_token_price(&token_name)
             .await
-            .map_err(|e| reject::custom(ApiError::DbError(e)))?
-            .map_or(0.0f64, |price| price.price);
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
 
         Ok(gen_arbitrary_response(ResponseBody::TokenPrice(
-            TokenPrice { token_price: price },
+            match price {
+                Some(price) => TokenPrice {
+                    token_price: price.price.unwrap_or(0.0),
+                    updated_at: Some(price.updated_at),
+                    stale: price.stale,
+                },
+                None => TokenPrice {
+                    token_price: 0.0,
+                    updated_at: None,
+                    stale: true,
+                },
+            },
+        )))
+    }
+
+    /// Get price history of the token
+    ///
+    /// Gets the polled prices of the token within the specified time boundaries,
+    /// time boundaries are specified as UNIX timestamps in UTC
+    #[utoipa::path(
+        tag="token",
+        get,
+        path = "/api/token/price/history/{token_name}/{start}/{end}",
+        responses(
+            (status = 200, description = "Token price history", body = TokenPriceHistory),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+        params(
+            ("token_name" = String, Path, description = "Name of the token, always uppercase"),
+            ("start" = u64, Path, description = "Starting timestamp for the search"),
+            ("end" = u64, Path, description = "Ending timestamp for the search"),
+        ),
+    )]
+    pub async fn get_token_price_history(
+        token_name: String,
+        start: u64,
+        end: u64,
+        db: DB,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        if start >= end {
+            return Err(reject::custom(ApiError::BadRange));
+        }
+
+        let prices = db
+            .query_token_price_history(
+                &token_name,
+                Utc.timestamp_opt(start as i64, 0).unwrap(),
+                Utc.timestamp_opt(end as i64, 0).unwrap(),
+                PRICE_HISTORY_LIMIT,
+            )
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+
+        Ok(gen_arbitrary_response(ResponseBody::TokenPriceHistory(
+            TokenPriceHistory { prices },
         )))
     }
 }
@@ -342,6 +498,100 @@ pub mod nickname {
 
         Ok(gen_info_response("The nickname has been changed"))
     }
+
+    /// Set player nickname with a session
+    ///
+    /// Sets the nickname of the wallet signed in with ethereum
+    #[utoipa::path(
+        tag="nickname",
+        post,
+        path = "/api/player/nickname/session",
+        request_body = SessionNickname,
+        responses(
+            (status = 200, description = "Nickname was set", body = InfoText),
+            (status = 401, description = "No valid player session", body = ErrorText),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+    )]
+    pub async fn set_session_nickname(
+        wallet: String,
+        data: SessionNickname,
+        db: DB,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        db.set_nickname(&wallet, &data.nickname)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+
+        Ok(gen_info_response("The nickname has been changed"))
+    }
+}
+
+pub mod auth {
+    use crate::config::{JWT_PLAYER_AUDIENCE, PLAYER_SESSION_TTL, SIWE_NONCE_TTL};
+    use crate::jwt::JWT_KEYS;
+    use crate::tools::random_token;
+    use chrono::{Duration, Utc};
+
+    use super::*;
+
+    /// Get sign-in nonce
+    ///
+    /// Issues a single use nonce for a sign-in with ethereum (EIP-4361) message
+    #[utoipa::path(
+        tag="auth",
+        get,
+        path = "/api/auth/nonce",
+        responses(
+            (status = 200, description = "Nonce", body = SiweNonce),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+    )]
+    pub async fn get_siwe_nonce(db: DB) -> Result<WarpResponse, warp::Rejection> {
+        let nonce = random_token();
+        let expires_at = Utc::now() + Duration::seconds(*SIWE_NONCE_TTL as i64);
+        db.add_siwe_nonce(&nonce, expires_at)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+
+        Ok(gen_arbitrary_response(ResponseBody::SiweNonce(SiweNonce {
+            nonce,
+            expires_at,
+        })))
+    }
+
+    /// Sign in player
+    ///
+    /// Opens a player session for the wallet which signed the sign-in with ethereum message
+    #[utoipa::path(
+        tag="auth",
+        post,
+        path = "/api/auth/login",
+        request_body = SiweLogin,
+        responses(
+            (status = 200, description = "Player session", body = PlayerSession),
+            (status = 400, description = "Invalid message or signature", body = ErrorText),
+            (status = 401, description = "Unknown, used or expired nonce", body = ErrorText),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+    )]
+    pub async fn login_player(wallet: String) -> Result<WarpResponse, warp::Rejection> {
+        let access_token = JWT_KEYS.generate_token(&JWT_KEYS.payload(
+            wallet.clone(),
+            &JWT_PLAYER_AUDIENCE,
+            0,
+            Utc::now().timestamp() as u64,
+            *PLAYER_SESSION_TTL,
+        ));
+
+        Ok(gen_arbitrary_response(ResponseBody::PlayerSession(
+            PlayerSession {
+                wallet,
+                access_token,
+                token_type: "Bearer".into(),
+                expires_in: *PLAYER_SESSION_TTL as usize,
+            },
+        )))
+    }
 }
 
 pub mod player {
@@ -373,254 +623,967 @@ pub mod player {
                 Default::default()
             });
 
-        Ok(gen_arbitrary_response(ResponseBody::Player(player)))
+        Ok(gen_arbitrary_response(ResponseBody::Player(player)))
+    }
+
+    /// Get user totals
+    ///
+    /// Gets user's statistics
+    #[utoipa::path(
+        tag="player",
+        get,
+        path = "/api/player/totals/{address}",
+        responses(
+            (status = 200, description = "User statistics", body = PlayerTotals),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+        params(
+            ("address" = String, Path, description = "User address")
+        ),
+    )]
+    pub async fn get_player_totals(
+        address: String,
+        db: DB,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        let totals = db
+            .query_player_totals(&address)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+
+        Ok(gen_arbitrary_response(ResponseBody::PlayerTotals(totals)))
+    }
+
+    /// Get latest games of the user
+    ///
+    /// Gets 2 latest games played by a user
+    #[utoipa::path(
+        tag="player",
+        get,
+        path = "/api/player/latest_games/{address}",
+        responses(
+            (status = 200, description = "Latest games", body = LatestGames),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+        params(
+            ("address" = String, Path, description = "User address")
+        ),
+    )]
+    pub async fn get_latest_games(
+        address: String,
+        db: DB,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        let latest_games = db
+            .get_latest_games(&address)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+
+        Ok(gen_arbitrary_response(ResponseBody::LatestGames(
+            LatestGames {
+                games: latest_games,
+            },
+        )))
+    }
+
+    /// Subscribe to the referal owner
+    ///
+    /// Subscribes to the owner of the referal wallet
+    #[utoipa::path(
+        tag="referal",
+        get,
+        path = "/api/player/referal/subscribe",
+        responses(
+            (status = 200, description = "Ok", body = InfoText),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+        params(
+        ),
+    )]
+    pub async fn create_referal(
+        data: json_requests::CreateReferal,
+        db: DB,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        if data.refer_to.to_lowercase() == data.referal.to_lowercase() {
+            return Err(reject::custom(ApiError::ArbitraryError(
+                "Referer and referal are the same".into(),
+            )));
+        }
+        db.create_referal(&data.refer_to, &data.referal)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+
+        Ok(gen_info_response("Referal has been created"))
+    }
+}
+
+pub mod bets {
+    use super::*;
+    /// Get player bets
+    ///
+    /// Gets bets of the player by player address, max amount of returned bets per call is 10
+    #[utoipa::path(
+        tag="bets",
+        get,
+        path = "/api/bets/player/{address}/{last_id}",
+        responses(
+            (status = 200, description = "User's bets", body = Bets),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+        params(
+            ("address" = String, Path, description = "User address"),
+            ("last_id" = Option<i64>, Path, description = "last bet id")
+        ),
+    )]
+    pub async fn get_player_bets(
+        address: String,
+        last_id: Option<i64>,
+        db: DB,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        let bets = db
+            .query_bets_for_address(&address, last_id, *config::PAGE_SIZE)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+
+        Ok(gen_arbitrary_response(ResponseBody::Bets(Bets { bets })))
+    }
+
+    /// Get player bets in increasing order
+    ///
+    /// Gets bets of the player by player address, max amount of returned bets per call is 10. Bets are returned in increasing order.
+    #[utoipa::path(
+        tag="bets",
+        get,
+        path = "/api/bets/player/inc/{address}/{last_id}",
+        responses(
+            (status = 200, description = "User's bets", body = Bets),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+        params(
+            ("address" = String, Path, description = "User address"),
+            ("last_id" = Option<i64>, Path, description = "last bet id")
+        ),
+    )]
+    pub async fn get_player_bets_inc(
+        address: String,
+        first_id: Option<i64>,
+        db: DB,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        let bets = db
+            .query_bets_for_address_inc(&address, first_id, *config::PAGE_SIZE)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+
+        Ok(gen_arbitrary_response(ResponseBody::Bets(Bets { bets })))
+    }
+
+    pub async fn get_game_bets(game_id: i64, db: DB) -> Result<WarpResponse, warp::Rejection> {
+        let bets = db
+            .query_bets_for_game(game_id, *config::PAGE_SIZE)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+
+        Ok(gen_arbitrary_response(ResponseBody::Bets(Bets { bets })))
+    }
+
+    /// Get all last bets for a game
+    ///
+    /// Gets 10 of the latest bets from the game
+    #[utoipa::path(
+        tag="bets",
+        get,
+        path = "/api/bets/game/{game_name}",
+        responses(
+            (status = 200, description = "Bets", body = Bets),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+        params(
+            ("game_name" = String, Path, description = "Name of the game")
+        ),
+    )]
+    pub async fn get_bets_for_game(
+        game_name: String,
+        db: DB,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        let bets = db
+            .query_bets_for_game_name(&game_name, *config::PAGE_SIZE)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+
+        Ok(gen_arbitrary_response(ResponseBody::Bets(Bets { bets })))
+    }
+
+    pub async fn get_network_bets(
+        netowork_id: i64,
+        db: DB,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        let bets = db
+            .query_bets_for_network(netowork_id, *config::PAGE_SIZE)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+
+        Ok(gen_arbitrary_response(ResponseBody::Bets(Bets { bets })))
+    }
+
+    /// Get all last bets
+    ///
+    /// Gets 10 of the latest bets from all networks for all games
+    #[utoipa::path(
+        tag="bets",
+        get,
+        path = "/api/bets/list",
+        responses(
+            (status = 200, description = "Bets", body = Bets),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+    )]
+    pub async fn get_all_last_bets(db: DB) -> Result<WarpResponse, warp::Rejection> {
+        let bets = db
+            .query_all_latest_bets(*config::PAGE_SIZE)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+
+        Ok(gen_arbitrary_response(ResponseBody::Bets(Bets { bets })))
+    }
+}
+
+pub mod abi {
+    use super::*;
+    pub async fn get_abi(signature: String, db: DB) -> Result<WarpResponse, warp::Rejection> {
+        let abi = db
+            .query_abi(&signature)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+
+        Ok(gen_arbitrary_response(ResponseBody::Abi(abi)))
+    }
+}
+
+pub mod admin {
+    use super::*;
+    use crate::communication::{RegistryChange, RegistrySender};
+    use crate::game_abi::{
+        parse_address, parse_multiplier_precision, parse_signature, validate_abi,
+    };
+    use crate::models::db_models::{GameAbi, NetworkInfo};
+    use crate::models::json_requests::{NetworkUrl, NewGame, NewToken};
+    use crate::models::json_responses::{
+        CreatedId, DeadLettersReprocessed, LogDeadLetters, MultipliersRecomputed,
+    };
+    use crate::network_handler;
+    use crate::price_oracle::MAX_DECIMALS;
+
+    /// Makes the network handlers pick up the changes
+    fn notify_networks(
+        registry: &RegistrySender,
+        change: fn(i64) -> RegistryChange,
+        network_ids: &[i64],
+    ) {
+        for network_id in network_ids {
+            if let Err(e) = registry.send(change(*network_id)) {
+                error!("Error notifying network {} change {:?}", network_id, e);
+            }
+        }
+    }
+
+    /// Add network
+    ///
+    /// Adds a new network with its native currency, requires the admin token
+    #[utoipa::path(
+        tag="admin",
+        post,
+        path = "/api/admin/network/add",
+        request_body = NetworkInfo,
+        responses(
+            (status = 200, description = "Network was added", body = InfoText),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+    )]
+    pub async fn add_network(
+        network: NetworkInfo,
+        db: DB,
+        registry: RegistrySender,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        db.add_network(&network)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+        notify_networks(&registry, RegistryChange::Network, &[network.network_id]);
+
+        Ok(gen_info_response("Network was added"))
+    }
+
+    /// Update network
+    ///
+    /// Updates the network with the chain ID from the body, requires the admin token
+    #[utoipa::path(
+        tag="admin",
+        post,
+        path = "/api/admin/network/update",
+        request_body = NetworkInfo,
+        responses(
+            (status = 200, description = "Network was updated", body = InfoText),
+            (status = 404, description = "Network wasn't found", body = ErrorText),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+    )]
+    pub async fn update_network(
+        network: NetworkInfo,
+        db: DB,
+        registry: RegistrySender,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        if !db
+            .update_network(&network)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?
+        {
+            return Err(reject::custom(ApiError::NotFound(
+                "network",
+                network.network_id.to_string(),
+            )));
+        }
+        notify_networks(&registry, RegistryChange::Network, &[network.network_id]);
+
+        Ok(gen_info_response("Network was updated"))
+    }
+
+    /// Delete network
+    ///
+    /// Deletes the network, its rpcs, explorers, tokens and games have to be deleted first,
+    /// requires the admin token
+    #[utoipa::path(
+        tag="admin",
+        post,
+        path = "/api/admin/network/delete/{network_id}",
+        responses(
+            (status = 200, description = "Network was deleted", body = InfoText),
+            (status = 404, description = "Network wasn't found", body = ErrorText),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+        params(
+            ("network_id" = i64, Path, description = "Chain ID of the network")
+        ),
+    )]
+    pub async fn delete_network(
+        network_id: i64,
+        db: DB,
+        registry: RegistrySender,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        if !db
+            .delete_network(network_id)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?
+        {
+            return Err(reject::custom(ApiError::NotFound(
+                "network",
+                network_id.to_string(),
+            )));
+        }
+        notify_networks(&registry, RegistryChange::Network, &[network_id]);
+
+        Ok(gen_info_response("Network was deleted"))
+    }
+
+    /// Add rpc
+    ///
+    /// Adds a new rpc to the network, requires the admin token
+    #[utoipa::path(
+        tag="admin",
+        post,
+        path = "/api/admin/rpc/add",
+        request_body = NetworkUrl,
+        responses(
+            (status = 200, description = "Rpc was added", body = CreatedId),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+    )]
+    pub async fn add_rpc(
+        rpc: NetworkUrl,
+        db: DB,
+        registry: RegistrySender,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        let id = db
+            .add_rpc(rpc.network_id, &rpc.url)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+        notify_networks(&registry, RegistryChange::Network, &[rpc.network_id]);
+
+        Ok(gen_arbitrary_response(ResponseBody::CreatedId(CreatedId {
+            id,
+        })))
+    }
+
+    /// Update rpc
+    ///
+    /// Replaces the rpc with the one from the body, requires the admin token
+    #[utoipa::path(
+        tag="admin",
+        post,
+        path = "/api/admin/rpc/update/{id}",
+        request_body = NetworkUrl,
+        responses(
+            (status = 200, description = "Rpc was updated", body = InfoText),
+            (status = 404, description = "Rpc wasn't found", body = ErrorText),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+        params(
+            ("id" = i64, Path, description = "ID of the rpc")
+        ),
+    )]
+    pub async fn update_rpc(
+        id: i64,
+        rpc: NetworkUrl,
+        db: DB,
+        registry: RegistrySender,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        let old_network_id = db
+            .update_rpc(id, rpc.network_id, &rpc.url)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?
+            .ok_or_else(|| reject::custom(ApiError::NotFound("rpc", id.to_string())))?;
+        notify_networks(
+            &registry,
+            RegistryChange::Network,
+            &[old_network_id, rpc.network_id],
+        );
+
+        Ok(gen_info_response("Rpc was updated"))
+    }
+
+    /// Delete rpc
+    ///
+    /// Deletes the rpc, requires the admin token
+    #[utoipa::path(
+        tag="admin",
+        post,
+        path = "/api/admin/rpc/delete/{id}",
+        responses(
+            (status = 200, description = "Rpc was deleted", body = InfoText),
+            (status = 404, description = "Rpc wasn't found", body = ErrorText),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+        params(
+            ("id" = i64, Path, description = "ID of the rpc")
+        ),
+    )]
+    pub async fn delete_rpc(
+        id: i64,
+        db: DB,
+        registry: RegistrySender,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        let network_id = db
+            .delete_rpc(id)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?
+            .ok_or_else(|| reject::custom(ApiError::NotFound("rpc", id.to_string())))?;
+        notify_networks(&registry, RegistryChange::Network, &[network_id]);
+
+        Ok(gen_info_response("Rpc was deleted"))
+    }
+
+    /// Add block explorer
+    ///
+    /// Adds a new block explorer to the network, requires the admin token
+    #[utoipa::path(
+        tag="admin",
+        post,
+        path = "/api/admin/block_explorer/add",
+        request_body = NetworkUrl,
+        responses(
+            (status = 200, description = "Block explorer was added", body = CreatedId),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+    )]
+    pub async fn add_block_explorer(
+        block_explorer: NetworkUrl,
+        db: DB,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        let id = db
+            .add_block_explorer(block_explorer.network_id, &block_explorer.url)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+
+        Ok(gen_arbitrary_response(ResponseBody::CreatedId(CreatedId {
+            id,
+        })))
+    }
+
+    /// Update block explorer
+    ///
+    /// Replaces the block explorer with the one from the body, requires the admin token
+    #[utoipa::path(
+        tag="admin",
+        post,
+        path = "/api/admin/block_explorer/update/{id}",
+        request_body = NetworkUrl,
+        responses(
+            (status = 200, description = "Block explorer was updated", body = InfoText),
+            (status = 404, description = "Block explorer wasn't found", body = ErrorText),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+        params(
+            ("id" = i64, Path, description = "ID of the block explorer")
+        ),
+    )]
+    pub async fn update_block_explorer(
+        id: i64,
+        block_explorer: NetworkUrl,
+        db: DB,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        if !db
+            .update_block_explorer(id, block_explorer.network_id, &block_explorer.url)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?
+        {
+            return Err(reject::custom(ApiError::NotFound(
+                "block explorer",
+                id.to_string(),
+            )));
+        }
+
+        Ok(gen_info_response("Block explorer was updated"))
+    }
+
+    /// Delete block explorer
+    ///
+    /// Deletes the block explorer, requires the admin token
+    #[utoipa::path(
+        tag="admin",
+        post,
+        path = "/api/admin/block_explorer/delete/{id}",
+        responses(
+            (status = 200, description = "Block explorer was deleted", body = InfoText),
+            (status = 404, description = "Block explorer wasn't found", body = ErrorText),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+        params(
+            ("id" = i64, Path, description = "ID of the block explorer")
+        ),
+    )]
+    pub async fn delete_block_explorer(id: i64, db: DB) -> Result<WarpResponse, warp::Rejection> {
+        if !db
+            .delete_block_explorer(id)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?
+        {
+            return Err(reject::custom(ApiError::NotFound(
+                "block explorer",
+                id.to_string(),
+            )));
+        }
+
+        Ok(gen_info_response("Block explorer was deleted"))
+    }
+
+    /// The prices of the tokens with more decimals can't be computed
+    fn check_decimals(decimals: i64) -> Result<(), warp::Rejection> {
+        if !(0..=MAX_DECIMALS).contains(&decimals) {
+            return Err(reject::custom(ApiError::BadDecimals(
+                decimals,
+                MAX_DECIMALS,
+            )));
+        }
+        Ok(())
+    }
+
+    /// Add token
+    ///
+    /// Adds a new token to the network, requires the admin token
+    #[utoipa::path(
+        tag="admin",
+        post,
+        path = "/api/admin/token/add",
+        request_body = NewToken,
+        responses(
+            (status = 200, description = "Token was added", body = CreatedId),
+            (status = 400, description = "Invalid token", body = ErrorText),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+    )]
+    pub async fn add_token(
+        token: NewToken,
+        db: DB,
+        registry: RegistrySender,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        check_decimals(token.decimals)?;
+        let id = db
+            .add_token(&token)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+        notify_networks(&registry, RegistryChange::Network, &[token.network_id]);
+
+        Ok(gen_arbitrary_response(ResponseBody::CreatedId(CreatedId {
+            id,
+        })))
+    }
+
+    /// Update token
+    ///
+    /// Replaces the token with the one from the body, requires the admin token
+    #[utoipa::path(
+        tag="admin",
+        post,
+        path = "/api/admin/token/update/{id}",
+        request_body = NewToken,
+        responses(
+            (status = 200, description = "Token was updated", body = InfoText),
+            (status = 404, description = "Token wasn't found", body = ErrorText),
+            (status = 400, description = "Invalid token", body = ErrorText),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+        params(
+            ("id" = i64, Path, description = "ID of the token")
+        ),
+    )]
+    pub async fn update_token(
+        id: i64,
+        token: NewToken,
+        db: DB,
+        registry: RegistrySender,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        check_decimals(token.decimals)?;
+        let old_network_id = db
+            .update_token(id, &token)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?
+            .ok_or_else(|| reject::custom(ApiError::NotFound("token", id.to_string())))?;
+        notify_networks(
+            &registry,
+            RegistryChange::Network,
+            &[old_network_id, token.network_id],
+        );
+
+        Ok(gen_info_response("Token was updated"))
+    }
+
+    /// Delete token
+    ///
+    /// Deletes the token, requires the admin token
+    #[utoipa::path(
+        tag="admin",
+        post,
+        path = "/api/admin/token/delete/{id}",
+        responses(
+            (status = 200, description = "Token was deleted", body = InfoText),
+            (status = 404, description = "Token wasn't found", body = ErrorText),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+        params(
+            ("id" = i64, Path, description = "ID of the token")
+        ),
+    )]
+    pub async fn delete_token(
+        id: i64,
+        db: DB,
+        registry: RegistrySender,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        let network_id = db
+            .delete_token(id)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?
+            .ok_or_else(|| reject::custom(ApiError::NotFound("token", id.to_string())))?;
+        notify_networks(&registry, RegistryChange::Network, &[network_id]);
+
+        Ok(gen_info_response("Token was deleted"))
+    }
+
+    /// Add game
+    ///
+    /// Adds a new game to the network, requires the admin token
+    #[utoipa::path(
+        tag="admin",
+        post,
+        path = "/api/admin/game/add",
+        request_body = NewGame,
+        responses(
+            (status = 200, description = "Game was added", body = CreatedId),
+            (status = 400, description = "Invalid game", body = ErrorText),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+    )]
+    pub async fn add_game(
+        game: NewGame,
+        db: DB,
+        registry: RegistrySender,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        parse_address(&game.address)
+            .and(parse_signature(&game.result_event_signature))
+            .and(parse_multiplier_precision(game.multiplier_precision))
+            .map_err(|e| reject::custom(ApiError::InvalidGame(e)))?;
+        let id = db
+            .add_game(&game)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+        notify_networks(&registry, RegistryChange::Games, &[game.network_id]);
+
+        Ok(gen_arbitrary_response(ResponseBody::CreatedId(CreatedId {
+            id,
+        })))
+    }
+
+    /// Update game
+    ///
+    /// Replaces the game with the one from the body, requires the admin token
+    #[utoipa::path(
+        tag="admin",
+        post,
+        path = "/api/admin/game/update/{id}",
+        request_body = NewGame,
+        responses(
+            (status = 200, description = "Game was updated", body = InfoText),
+            (status = 404, description = "Game wasn't found", body = ErrorText),
+            (status = 400, description = "Invalid game", body = ErrorText),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+        params(
+            ("id" = i64, Path, description = "ID of the game")
+        ),
+    )]
+    pub async fn update_game(
+        id: i64,
+        game: NewGame,
+        db: DB,
+        registry: RegistrySender,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        parse_address(&game.address)
+            .and(parse_signature(&game.result_event_signature))
+            .and(parse_multiplier_precision(game.multiplier_precision))
+            .map_err(|e| reject::custom(ApiError::InvalidGame(e)))?;
+        let old_network_id = db
+            .update_game(id, &game)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?
+            .ok_or_else(|| reject::custom(ApiError::NotFound("game", id.to_string())))?;
+        notify_networks(
+            &registry,
+            RegistryChange::Games,
+            &[old_network_id, game.network_id],
+        );
+
+        Ok(gen_info_response("Game was updated"))
     }
 
-    /// Get user totals
+    /// Delete game
     ///
-    /// Gets user's statistics
+    /// Deletes the game, fails while the game has bets, requires the admin token
     #[utoipa::path(
-        tag="player",
-        get,
-        path = "/api/player/totals/{address}",
+        tag="admin",
+        post,
+        path = "/api/admin/game/delete/{id}",
         responses(
-            (status = 200, description = "User statistics", body = PlayerTotals),
+            (status = 200, description = "Game was deleted", body = InfoText),
+            (status = 404, description = "Game wasn't found", body = ErrorText),
             (status = 500, description = "Internal server error", body = ErrorText),
         ),
         params(
-            ("address" = String, Path, description = "User address")
+            ("id" = i64, Path, description = "ID of the game")
         ),
     )]
-    pub async fn get_player_totals(
-        address: String,
+    pub async fn delete_game(
+        id: i64,
         db: DB,
+        registry: RegistrySender,
     ) -> Result<WarpResponse, warp::Rejection> {
-        let totals = db
-            .query_player_totals(&address)
+        let network_id = db
+            .delete_game(id)
             .await
-            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?
+            .ok_or_else(|| reject::custom(ApiError::NotFound("game", id.to_string())))?;
+        notify_networks(&registry, RegistryChange::Games, &[network_id]);
 
-        Ok(gen_arbitrary_response(ResponseBody::PlayerTotals(totals)))
+        Ok(gen_info_response("Game was deleted"))
     }
 
-    /// Get latest games of the user
+    /// Add game abi
     ///
-    /// Gets 2 latest games played by a user
+    /// Adds the layout of a game's result event, requires the admin token
     #[utoipa::path(
-        tag="player",
-        get,
-        path = "/api/player/latest_games/{address}",
+        tag="admin",
+        post,
+        path = "/api/admin/abi/add",
+        request_body = GameAbi,
         responses(
-            (status = 200, description = "Latest games", body = LatestGames),
+            (status = 200, description = "Abi was added", body = InfoText),
+            (status = 400, description = "Invalid game", body = ErrorText),
             (status = 500, description = "Internal server error", body = ErrorText),
         ),
-        params(
-            ("address" = String, Path, description = "User address")
-        ),
     )]
-    pub async fn get_latest_games(
-        address: String,
-        db: DB,
-    ) -> Result<WarpResponse, warp::Rejection> {
-        let latest_games = db
-            .get_latest_games(&address)
+    pub async fn add_game_abi(abi: GameAbi, db: DB) -> Result<WarpResponse, warp::Rejection> {
+        validate_abi(&abi).map_err(|e| reject::custom(ApiError::InvalidGame(e)))?;
+        db.add_game_abi(&abi)
             .await
             .map_err(|e| reject::custom(ApiError::DbError(e)))?;
 
-        Ok(gen_arbitrary_response(ResponseBody::LatestGames(
-            LatestGames {
-                games: latest_games,
-            },
-        )))
+        Ok(gen_info_response("Abi was added"))
     }
 
-    /// Subscribe to the referal owner
+    /// Update game abi
     ///
-    /// Subscribes to the owner of the referal wallet
+    /// Updates the abi with the signature from the body, requires the admin token
     #[utoipa::path(
-        tag="referal",
-        get,
-        path = "/api/player/referal/subscribe",
+        tag="admin",
+        post,
+        path = "/api/admin/abi/update",
+        request_body = GameAbi,
         responses(
-            (status = 200, description = "Ok", body = InfoText),
+            (status = 200, description = "Abi was updated", body = InfoText),
+            (status = 404, description = "Abi wasn't found", body = ErrorText),
+            (status = 400, description = "Invalid game", body = ErrorText),
             (status = 500, description = "Internal server error", body = ErrorText),
         ),
-        params(
-        ),
     )]
-    pub async fn create_referal(
-        data: json_requests::CreateReferal,
+    pub async fn update_game_abi(
+        abi: GameAbi,
         db: DB,
+        registry: RegistrySender,
     ) -> Result<WarpResponse, warp::Rejection> {
-        if data.refer_to.to_lowercase() == data.referal.to_lowercase() {
-            return Err(reject::custom(ApiError::ArbitraryError(
-                "Referer and referal are the same".into(),
-            )));
-        }
-        db.create_referal(&data.refer_to, &data.referal)
+        validate_abi(&abi).map_err(|e| reject::custom(ApiError::InvalidGame(e)))?;
+        let network_ids = db
+            .update_game_abi(&abi)
             .await
-            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?
+            .ok_or_else(|| reject::custom(ApiError::NotFound("abi", abi.signature.clone())))?;
+        notify_networks(&registry, RegistryChange::Games, &network_ids);
 
-        Ok(gen_info_response("Referal has been created"))
+        Ok(gen_info_response("Abi was updated"))
     }
-}
 
-pub mod bets {
-    use super::*;
-    /// Get player bets
+    /// Delete game abi
     ///
-    /// Gets bets of the player by player address, max amount of returned bets per call is 10
+    /// Deletes the abi, fails while games use it, requires the admin token
     #[utoipa::path(
-        tag="bets",
-        get,
-        path = "/api/bets/player/{address}/{last_id}",
+        tag="admin",
+        post,
+        path = "/api/admin/abi/delete/{signature}",
         responses(
-            (status = 200, description = "User's bets", body = Bets),
+            (status = 200, description = "Abi was deleted", body = InfoText),
+            (status = 404, description = "Abi wasn't found", body = ErrorText),
             (status = 500, description = "Internal server error", body = ErrorText),
         ),
         params(
-            ("address" = String, Path, description = "User address"),
-            ("last_id" = Option<i64>, Path, description = "last bet id")
+            ("signature" = String, Path, description = "Signature of the event")
         ),
     )]
-    pub async fn get_player_bets(
-        address: String,
-        last_id: Option<i64>,
+    pub async fn delete_game_abi(
+        signature: String,
         db: DB,
     ) -> Result<WarpResponse, warp::Rejection> {
-        let bets = db
-            .query_bets_for_address(&address, last_id, *config::PAGE_SIZE)
+        if !db
+            .delete_game_abi(&signature)
             .await
-            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?
+        {
+            return Err(reject::custom(ApiError::NotFound("abi", signature)));
+        }
 
-        Ok(gen_arbitrary_response(ResponseBody::Bets(Bets { bets })))
+        Ok(gen_info_response("Abi was deleted"))
     }
 
-    /// Get player bets in increasing order
+    /// Get dead letters of the network
     ///
-    /// Gets bets of the player by player address, max amount of returned bets per call is 10. Bets are returned in increasing order.
+    /// Gets the game logs of the network which couldn't be turned into bets, newest first,
+    /// requires the admin token
     #[utoipa::path(
-        tag="bets",
-        get,
-        path = "/api/bets/player/inc/{address}/{last_id}",
+        tag="admin",
+        post,
+        path = "/api/admin/dead_letter/list/{network_id}/{last_id}",
         responses(
-            (status = 200, description = "User's bets", body = Bets),
+            (status = 200, description = "Dead letters", body = LogDeadLetters),
             (status = 500, description = "Internal server error", body = ErrorText),
         ),
         params(
-            ("address" = String, Path, description = "User address"),
-            ("last_id" = Option<i64>, Path, description = "last bet id")
+            ("network_id" = i64, Path, description = "Chain ID of the network"),
+            ("last_id" = Option<i64>, Path, description = "last dead letter id")
         ),
     )]
-    pub async fn get_player_bets_inc(
-        address: String,
-        first_id: Option<i64>,
+    pub async fn get_dead_letters(
+        network_id: i64,
+        last_id: Option<i64>,
         db: DB,
     ) -> Result<WarpResponse, warp::Rejection> {
-        let bets = db
-            .query_bets_for_address_inc(&address, first_id, *config::PAGE_SIZE)
-            .await
-            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
-
-        Ok(gen_arbitrary_response(ResponseBody::Bets(Bets { bets })))
-    }
-
-    pub async fn get_game_bets(game_id: i64, db: DB) -> Result<WarpResponse, warp::Rejection> {
-        let bets = db
-            .query_bets_for_game(game_id, *config::PAGE_SIZE)
+        let dead_letters = db
+            .query_log_dead_letters(network_id, last_id, *config::PAGE_SIZE)
             .await
             .map_err(|e| reject::custom(ApiError::DbError(e)))?;
 
-        Ok(gen_arbitrary_response(ResponseBody::Bets(Bets { bets })))
+        Ok(gen_arbitrary_response(ResponseBody::LogDeadLetters(
+            LogDeadLetters { dead_letters },
+        )))
     }
 
-    /// Get all last bets for a game
+    /// Reprocess dead letters of the network
     ///
-    /// Gets 10 of the latest bets from the game
+    /// Decodes the dead letters of the network again with its current games,
+    /// meant to be called after fixing the abi of a game, requires the admin token
     #[utoipa::path(
-        tag="bets",
-        get,
-        path = "/api/bets/game/{game_name}",
+        tag="admin",
+        post,
+        path = "/api/admin/dead_letter/reprocess/{network_id}",
         responses(
-            (status = 200, description = "Bets", body = Bets),
+            (status = 200, description = "Dead letters were reprocessed", body = DeadLettersReprocessed),
+            (status = 404, description = "Network wasn't found", body = ErrorText),
             (status = 500, description = "Internal server error", body = ErrorText),
         ),
         params(
-            ("game_name" = String, Path, description = "Name of the game")
+            ("network_id" = i64, Path, description = "Chain ID of the network")
         ),
     )]
-    pub async fn get_bets_for_game(
-        game_name: String,
+    pub async fn reprocess_dead_letters(
+        network_id: i64,
         db: DB,
     ) -> Result<WarpResponse, warp::Rejection> {
-        let bets = db
-            .query_bets_for_game_name(&game_name, *config::PAGE_SIZE)
+        let network = db
+            .query_network(network_id)
             .await
-            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
-
-        Ok(gen_arbitrary_response(ResponseBody::Bets(Bets { bets })))
-    }
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?
+            .ok_or_else(|| reject::custom(ApiError::NotFound("network", network_id.to_string())))?;
 
-    pub async fn get_network_bets(
-        netowork_id: i64,
-        db: DB,
-    ) -> Result<WarpResponse, warp::Rejection> {
-        let bets = db
-            .query_bets_for_network(netowork_id, *config::PAGE_SIZE)
+        let (recovered, failed) = network_handler::reprocess_dead_letters(&db, &network)
             .await
             .map_err(|e| reject::custom(ApiError::DbError(e)))?;
 
-        Ok(gen_arbitrary_response(ResponseBody::Bets(Bets { bets })))
+        Ok(gen_arbitrary_response(
+            ResponseBody::DeadLettersReprocessed(DeadLettersReprocessed { recovered, failed }),
+        ))
     }
 
-    /// Get all last bets
+    /// Recompute legacy multipliers
     ///
-    /// Gets 10 of the latest bets from all networks for all games
+    /// Computes the multipliers of the bets stored before they were derived from the payout,
+    /// along with the highest multipliers of their players. Meant to be called once after
+    /// the upgrade, calling it again gives the same multipliers, requires the admin token
     #[utoipa::path(
-        tag="bets",
-        get,
-        path = "/api/bets/list",
+        tag="admin",
+        post,
+        path = "/api/admin/bet/recompute_multipliers",
         responses(
-            (status = 200, description = "Bets", body = Bets),
+            (status = 200, description = "Multipliers were recomputed", body = MultipliersRecomputed),
             (status = 500, description = "Internal server error", body = ErrorText),
         ),
     )]
-    pub async fn get_all_last_bets(db: DB) -> Result<WarpResponse, warp::Rejection> {
+    pub async fn recompute_multipliers(db: DB) -> Result<WarpResponse, warp::Rejection> {
         let bets = db
-            .query_all_latest_bets(*config::PAGE_SIZE)
-            .await
-            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
-
-        Ok(gen_arbitrary_response(ResponseBody::Bets(Bets { bets })))
-    }
-}
-
-pub mod abi {
-    use super::*;
-    pub async fn get_abi(signature: String, db: DB) -> Result<WarpResponse, warp::Rejection> {
-        let abi = db
-            .query_abi(&signature)
+            .recompute_legacy_multipliers()
             .await
             .map_err(|e| reject::custom(ApiError::DbError(e)))?;
 
-        Ok(gen_arbitrary_response(ResponseBody::Abi(abi)))
+        Ok(gen_arbitrary_response(ResponseBody::MultipliersRecomputed(
+            MultipliersRecomputed { bets },
+        )))
     }
 }
 
 pub mod partner {
 
-    use crate::config::PASSWORD_SALT;
-    use crate::jwt;
+    use crate::config::{ACCESS_TOKEN_TTL, JWT_PARTNER_AUDIENCE, REFRESH_TOKEN_TTL};
+    use crate::jwt::JWT_KEYS;
     use crate::models::db_models::{PlayersTotals, TimeBoundaries};
-    use crate::models::json_requests::WithdrawRequest;
+    use crate::models::json_requests::{RefreshTokenRequest, WithdrawRequest};
     use crate::models::json_responses::{
         ClicksTimeMapped, ConnectedWalletInfo, ConnectedWalletsTimeMapped, PartnerInfo,
         PartnerSiteInfo,
     };
-    use crate::tools::blake_hash;
-    use blake2::{Blake2b512, Digest};
-    use chrono::{TimeZone, Utc};
-    use hex::ToHex;
+    use crate::tools::{
+        blake_hash, hash_password, is_legacy_password_hash, random_token, verify_password,
+    };
+    use chrono::{Duration, TimeZone, Utc};
 
     use super::*;
 
@@ -641,13 +1604,6 @@ pub mod partner {
         data: json_requests::RegisterPartner,
         db: DB,
     ) -> Result<WarpResponse, warp::Rejection> {
-        let mut hasher = Blake2b512::new();
-
-        hasher.update(data.password.as_bytes());
-
-        let res = hasher.finalize().encode_hex();
-
-        debug!("res {:?}", res);
         db.create_partner(
             Partner {
                 name: data.name,
@@ -658,9 +1614,10 @@ pub mod partner {
                 program: PartnerProgram::firstMonth,
                 is_verified: false,
                 login: data.login,
-                password: res,
+                password: hash_password(&data.password).await,
                 registration_time: Default::default(),
                 language: data.language,
+                token_version: 0,
             },
             &[],
         )
@@ -1427,19 +2384,70 @@ pub mod partner {
         data: ChangePasswordRequest,
         db: DB,
     ) -> Result<WarpResponse, warp::Rejection> {
-        let old_hashed = blake_hash(&data.old_password);
-        let new_hashed = blake_hash(&data.new_password);
+        let partner = db
+            .get_partner(&wallet)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+        if !verify_password(&data.old_password, &partner.password).await {
+            return Err(reject::custom(ApiError::BadPassword));
+        }
         if !db
-            .partner_change_password(&wallet, &old_hashed, &new_hashed)
+            .partner_change_password(
+                &wallet,
+                &partner.password,
+                &hash_password(&data.new_password).await,
+            )
             .await
             .map_err(|e| reject::custom(ApiError::DbError(e)))?
         {
             return Err(reject::custom(ApiError::BadPassword));
         }
+        db.revoke_partner_refresh_tokens(&wallet)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
 
         Ok(gen_info_response("Password was changed successfully"))
     }
 
+    /// Issues an access token paired with the given refresh token
+    fn access_token(partner: &Partner, refresh_token: String) -> AccessToken {
+        let token = JWT_KEYS.generate_token(&JWT_KEYS.payload(
+            partner.main_wallet.clone(),
+            &JWT_PARTNER_AUDIENCE,
+            partner.token_version,
+            Utc::now().timestamp() as u64,
+            *ACCESS_TOKEN_TTL,
+        ));
+
+        AccessToken {
+            access_token: token,
+            token_type: "Bearer".into(),
+            expires_in: *ACCESS_TOKEN_TTL as usize,
+            refresh_token,
+        }
+    }
+
+    fn refresh_token_expiry() -> chrono::DateTime<Utc> {
+        Utc::now() + Duration::seconds(*REFRESH_TOKEN_TTL as i64)
+    }
+
+    /// Starts a new refresh token family for the partner
+    async fn open_session(partner: &Partner, db: &DB) -> Result<WarpResponse, warp::Rejection> {
+        let refresh_token = random_token();
+        db.add_refresh_token(
+            &partner.main_wallet,
+            &blake_hash(&refresh_token),
+            &random_token(),
+            refresh_token_expiry(),
+        )
+        .await
+        .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+
+        Ok(gen_arbitrary_response(ResponseBody::AccessToken(
+            access_token(partner, refresh_token),
+        )))
+    }
+
     /// Login partner
     ///
     /// Logins partner with provided login/password
@@ -1454,31 +2462,118 @@ pub mod partner {
         ),
     )]
     pub async fn login_partner(login: Login, db: DB) -> Result<WarpResponse, warp::Rejection> {
-        let hashed_password = blake_hash(&login.password);
         let partner = db
-            .login_partner(&login.login, &hashed_password)
+            .get_partner_by_login(&login.login)
             .await
             .map_err(|e| reject::custom(ApiError::DbError(e)))?
             .ok_or(reject::custom(ApiError::WrongLoginPassword))?;
+        if !verify_password(&login.password, &partner.password).await {
+            return Err(reject::custom(ApiError::WrongLoginPassword));
+        }
 
-        let token = jwt::generate_token(
-            &jwt::Payload {
-                iss: None,
-                sub: partner.login,
-                exp: 100,
-                iat: 100,
-                aud: "".into(),
-            },
-            &format!("{:?}{:?}", *PASSWORD_SALT, hashed_password),
-        );
+        if is_legacy_password_hash(&partner.password) {
+            if let Err(e) = db
+                .upgrade_partner_password(
+                    &partner.main_wallet,
+                    &partner.password,
+                    &hash_password(&login.password).await,
+                )
+                .await
+            {
+                error!(
+                    "Error upgrading the password hash of partner `{}`: {:?}",
+                    partner.main_wallet, e
+                );
+            }
+        }
+
+        open_session(&partner, &db).await
+    }
+
+    /// Login partner with wallet
+    ///
+    /// Logins partner with a sign-in with ethereum message signed by the main wallet
+    #[utoipa::path(
+        tag="partner",
+        post,
+        path = "/api/partner/login/wallet",
+        request_body = SiweLogin,
+        responses(
+            (status = 200, description = "Access token", body = AccessToken),
+            (status = 400, description = "Invalid message or signature", body = ErrorText),
+            (status = 401, description = "Unknown, used or expired nonce", body = ErrorText),
+            (status = 404, description = "No partner with this main wallet", body = ErrorText),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+    )]
+    pub async fn login_partner_wallet(
+        wallet: String,
+        db: DB,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        let partner = db
+            .get_partner_by_wallet(&wallet)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?
+            .ok_or_else(|| reject::custom(ApiError::NotFound("partner", wallet)))?;
+
+        open_session(&partner, &db).await
+    }
+
+    /// Refresh partner token
+    ///
+    /// Exchanges a refresh token for a new access token and a new refresh token.
+    /// Every refresh token can be used once, using it again revokes the whole session
+    #[utoipa::path(
+        tag="partner",
+        post,
+        path = "/api/partner/token/refresh",
+        request_body = RefreshTokenRequest,
+        responses(
+            (status = 200, description = "Access token", body = AccessToken),
+            (status = 401, description = "Refresh token is invalid, expired or reused", body = ErrorText),
+            (status = 500, description = "Internal server error", body = ErrorText),
+        ),
+    )]
+    pub async fn refresh_partner_token(
+        data: RefreshTokenRequest,
+        db: DB,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        let token_hash = blake_hash(&data.refresh_token);
+        let refresh_token = random_token();
+        let wallet = db
+            .rotate_refresh_token(
+                &token_hash,
+                &blake_hash(&refresh_token),
+                refresh_token_expiry(),
+            )
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+
+        let Some(wallet) = wallet else {
+            let stored = db
+                .query_refresh_token(&token_hash)
+                .await
+                .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+            if let Some(stored) = stored.filter(|t| t.used || t.revoked) {
+                warn!(
+                    "Refresh token reuse for partner `{}`, revoking its family",
+                    stored.partner_id
+                );
+                db.revoke_refresh_token_family(&stored.family)
+                    .await
+                    .map_err(|e| reject::custom(ApiError::DbError(e)))?;
+                return Err(reject::custom(ApiError::RefreshTokenReused));
+            }
+            return Err(reject::custom(ApiError::InvalidRefreshToken));
+        };
+
+        let partner = db
+            .get_partner(&wallet)
+            .await
+            .map_err(|e| reject::custom(ApiError::DbError(e)))?;
 
         Ok(gen_arbitrary_response(ResponseBody::AccessToken(
-            AccessToken {
-                access_token: token.clone(),
-                token_type: "Bearer".into(),
-                expires_in: 100,
-                refresh_token: token,
-            },
+            access_token(&partner, refresh_token),
         )))
     }
 }
@@ -1517,6 +2612,30 @@ pub mod general {
         Ok(gen_arbitrary_response(ResponseBody::Totals(totals)))
     }
 
+    /// Get db queue state
+    ///
+    /// Gets the depth of the queue between the networks handlers and the db writer,
+    /// the amount of retried writes and dead lettered bets
+    #[utoipa::path(
+        tag="general",
+        get,
+        path = "/api/general/db_queue",
+        responses(
+            (status = 200, description = "Db queue", body = DbQueue),
+        ),
+    )]
+    pub async fn get_db_queue(
+        db_queue: SharedDbQueueMetrics,
+    ) -> Result<WarpResponse, warp::Rejection> {
+        Ok(gen_arbitrary_response(ResponseBody::DbQueue(DbQueue {
+            depth: db_queue.depth.load(Ordering::Relaxed),
+            capacity: *config::DB_QUEUE_SIZE,
+            stalls: db_queue.stalls.load(Ordering::Relaxed),
+            retries: db_queue.retries.load(Ordering::Relaxed),
+            dead_letters: db_queue.dead_letters.load(Ordering::Relaxed),
+        })))
+    }
+
     /// Get leaderboard data
     ///
     /// Gets the leaderboard
@@ -1589,7 +2708,12 @@ pub async fn websockets_subscriptions_reader(
     }
 }
 
-pub async fn websockets_handler(socket: WebSocket, db: DB, mut channel: WsDataFeedReceiver) {
+pub async fn websockets_handler(
+    socket: WebSocket,
+    db: DB,
+    mut channel: WsDataFeedReceiver,
+    mut shutdown: ShutdownReceiver,
+) {
     debug!("New connection {:?}", &socket);
     let (mut ws_tx, ws_rx) = socket.split();
     let mut subscriptions: HashSet<String> = Default::default();
@@ -1610,7 +2734,7 @@ pub async fn websockets_handler(socket: WebSocket, db: DB, mut channel: WsDataFe
             bet = channel.recv() => {
                 match bet{
                     Ok(bet) => {
-                        if !subscribed_all && subscriptions.get(&bet.game_name).is_none(){
+                        if !subscribed_all && !subscriptions.contains(bet.game_name()){
                             continue;
                         }
 
@@ -1625,6 +2749,16 @@ pub async fn websockets_handler(socket: WebSocket, db: DB, mut channel: WsDataFe
                     },
                 }
             }
+            _ = shutdown_requested(&mut shutdown) => {
+                // 1001 Going Away
+                if let Err(e) = ws_tx
+                    .send(Message::close_with(1001u16, "Server is shutting down"))
+                    .await
+                {
+                    debug!("Error closing connection {:?}", e);
+                }
+                break;
+            }
             _ = sleep(Duration::from_millis(5000)) => {
                 ws_tx
                     .send(Message::text(serde_json::to_string(&WebsocketsIncommingMessage::Ping).unwrap()))
@@ -1,19 +1,29 @@
+use std::collections::BTreeMap;
+
+use crate::config::{JWT_ISSUER, JWT_KEY_ID, JWT_SECRETS};
 use hmac::{Hmac, Mac};
 pub use jwt::error::Error as JwtError;
-use jwt::SignWithKey;
-use jwt::VerifyWithKey;
+use jwt::{AlgorithmType, Header, SignWithKey, Token, VerifyWithStore};
+use lazy_static::lazy_static;
 use sha2::Sha256;
-use std::str;
+use thiserror::Error;
 
 use serde::{Deserialize, Serialize};
 
 type HS256 = Hmac<Sha256>;
 
+/// Shortest accepted secret, HS256 keys shouldn't be shorter than the hash output
+const MIN_SECRET_LEN: usize = 32;
+
+lazy_static! {
+    pub static ref JWT_KEYS: Keys = Keys::new(&JWT_KEY_ID, &JWT_SECRETS, &JWT_ISSUER);
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Payload {
     /// issuer
     pub iss: Option<String>,
-    /// user login
+    /// partner or player wallet
     pub sub: String,
     /// expiration time
     pub exp: u64,
@@ -21,14 +31,170 @@ pub struct Payload {
     pub iat: u64,
     /// audience
     pub aud: String,
+    /// token version of the partner, bumped to revoke the issued tokens
+    pub ver: i64,
 }
 
-pub fn generate_token(payload: &Payload, secret: &str) -> String {
-    let key: HS256 = Hmac::new_from_slice(secret.as_bytes()).unwrap();
-    payload.sign_with_key(&key).unwrap()
+#[derive(Error, Debug)]
+pub enum TokenError {
+    #[error("{0}")]
+    Jwt(#[from] JwtError),
+
+    #[error("Wrong token issuer")]
+    Issuer,
+
+    #[error("Wrong token audience")]
+    Audience,
+
+    #[error("Token expired")]
+    Expired,
 }
 
-pub fn verify_token(token: &str, secret: &str) -> Result<Payload, JwtError> {
-    let key: HS256 = Hmac::new_from_slice(secret.as_bytes()).unwrap();
-    token.verify_with_key(&key)
+/// Signing keys by their id, tokens are signed with the current key and verified
+/// with the key from their `kid` header, so retired keys can stay until their tokens expire
+pub struct Keys {
+    signing_key_id: String,
+    keys: BTreeMap<String, HS256>,
+    issuer: String,
+}
+
+impl Keys {
+    pub fn new(signing_key_id: &str, secrets: &BTreeMap<String, String>, issuer: &str) -> Self {
+        let keys: BTreeMap<String, HS256> = secrets
+            .iter()
+            .map(|(id, secret)| {
+                assert!(
+                    secret.len() >= MIN_SECRET_LEN,
+                    "The jwt secret `{}` is shorter than {} bytes",
+                    id,
+                    MIN_SECRET_LEN
+                );
+                (id.clone(), Hmac::new_from_slice(secret.as_bytes()).unwrap())
+            })
+            .collect();
+        assert!(
+            keys.contains_key(signing_key_id),
+            "No secret for the jwt signing key `{}`",
+            signing_key_id
+        );
+
+        Self {
+            signing_key_id: signing_key_id.into(),
+            keys,
+            issuer: issuer.into(),
+        }
+    }
+
+    pub fn payload(&self, sub: String, aud: &str, ver: i64, iat: u64, ttl: u64) -> Payload {
+        Payload {
+            iss: Some(self.issuer.clone()),
+            sub,
+            exp: iat + ttl,
+            iat,
+            aud: aud.into(),
+            ver,
+        }
+    }
+
+    pub fn generate_token(&self, payload: &Payload) -> String {
+        let header = Header {
+            algorithm: AlgorithmType::Hs256,
+            key_id: Some(self.signing_key_id.clone()),
+            ..Default::default()
+        };
+        Token::new(header, payload)
+            .sign_with_key(&self.keys[&self.signing_key_id])
+            .unwrap()
+            .as_str()
+            .into()
+    }
+
+    /// Checks the signature before any claim is read, then the issuer, audience and expiry
+    pub fn verify_token(&self, token: &str, aud: &str, now: u64) -> Result<Payload, TokenError> {
+        let payload: Payload = token.verify_with_store(&self.keys)?;
+        if payload.iss.as_deref() != Some(self.issuer.as_str()) {
+            return Err(TokenError::Issuer);
+        }
+        if payload.aud != aud {
+            return Err(TokenError::Audience);
+        }
+        if payload.exp <= now {
+            return Err(TokenError::Expired);
+        }
+
+        Ok(payload)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn key_store(signing_key_id: &str, secrets: &[(&str, &str)]) -> Keys {
+        let secrets = secrets
+            .iter()
+            .map(|(id, secret)| (id.to_string(), secret.repeat(MIN_SECRET_LEN)))
+            .collect();
+        Keys::new(signing_key_id, &secrets, "bicas")
+    }
+
+    #[test]
+    #[should_panic(expected = "shorter than 32 bytes")]
+    fn short_secret_test() {
+        let secrets = [("1".to_string(), "bicas".to_string())].into();
+        Keys::new("1", &secrets, "bicas");
+    }
+
+    #[test]
+    fn verify_issued_token_test() {
+        let keys = key_store("1", &[("1", "secret")]);
+        let token = keys.generate_token(&keys.payload("0x01".into(), "partner", 3, 1000, 60));
+
+        let payload = keys.verify_token(&token, "partner", 1059).unwrap();
+        assert_eq!(payload.sub, "0x01");
+        assert_eq!(payload.ver, 3);
+        assert!(matches!(
+            keys.verify_token(&token, "partner", 1060),
+            Err(TokenError::Expired)
+        ));
+    }
+
+    #[test]
+    fn verify_rotated_key_test() {
+        let old = key_store("1", &[("1", "old")]);
+        let rotated = key_store("2", &[("1", "old"), ("2", "new")]);
+        let token = old.generate_token(&old.payload("0x01".into(), "partner", 0, 1000, 60));
+        assert!(rotated.verify_token(&token, "partner", 1000).is_ok());
+
+        let retired = key_store("2", &[("2", "new")]);
+        assert!(matches!(
+            retired.verify_token(&token, "partner", 1000),
+            Err(TokenError::Jwt(JwtError::NoKeyWithKeyId(_)))
+        ));
+    }
+
+    #[test]
+    fn verify_foreign_token_test() {
+        let keys = key_store("1", &[("1", "secret")]);
+        let payload = keys.payload("0x01".into(), "partner", 0, 1000, 60);
+        let forged = key_store("1", &[("1", "other")]).generate_token(&payload);
+        assert!(matches!(
+            keys.verify_token(&forged, "partner", 1000),
+            Err(TokenError::Jwt(JwtError::RustCryptoMac(_)))
+        ));
+
+        let mut payload = keys.payload("0x01".into(), "partner", 0, 1000, 60);
+        payload.aud = "admin".into();
+        assert!(matches!(
+            keys.verify_token(&keys.generate_token(&payload), "partner", 1000),
+            Err(TokenError::Audience)
+        ));
+
+        let mut payload = keys.payload("0x01".into(), "partner", 0, 1000, 60);
+        payload.iss = None;
+        assert!(matches!(
+            keys.verify_token(&keys.generate_token(&payload), "partner", 1000),
+            Err(TokenError::Issuer)
+        ));
+    }
 }
@@ -7,6 +7,7 @@ use db::DB;
 use rejection_handler::handle_rejection;
 use std::env;
 use tokio::signal;
+use tokio::time::timeout;
 use tracing::{debug, error, info, warn};
 use tracing_subscriber::{fmt, prelude::__tracing_subscriber_SubscriberExt, EnvFilter};
 use utoipa::OpenApi;
@@ -20,22 +21,24 @@ mod config;
 mod db;
 mod errors;
 mod filters;
+mod game_abi;
 mod handlers;
 mod jwt;
 mod models;
 mod network_handler;
+mod price_oracle;
 mod rejection_handler;
+mod rpc_pool;
+mod siwe;
 mod tools;
 
 #[tokio::main]
 async fn main() {
     // load .env file
-    dotenvy::dotenv()
-        .map_err(|e| {
-            error!(error = e.to_string(), "Error loading .env");
-            e
-        })
-        .unwrap();
+    if let Err(e) = dotenvy::dotenv() {
+        error!(error = e.to_string(), "Error loading .env");
+        panic!("Error loading .env: {:?}", e);
+    }
 
     // load log config
     let env_filter = EnvFilter::from_default_env()
@@ -57,6 +60,9 @@ async fn main() {
 
     info!("Starting rest api");
 
+    // fail on a misconfigured signing key before serving
+    lazy_static::initialize(&jwt::JWT_KEYS);
+
     let db_settings = DatabaseSettings {
         username: env::var("DB_USERNAME").unwrap(),
         password: env::var("DB_PASSWORD").unwrap(),
@@ -77,9 +83,23 @@ async fn main() {
 
     let (bet_sender, bet_receiver) = channel(10000);
     let (ws_data_feed, _bet_receiver) = channel(10000);
+    let rpc_health = rpc_pool::RpcHealthRegistry::default();
+    let db_queue = SharedDbQueueMetrics::default();
+    let (registry_sender, registry_receiver) = unbounded_channel();
+    let (shutdown_sender, shutdown_receiver): (ShutdownSender, ShutdownReceiver) =
+        watch::channel(false);
 
     info!("Staring networks handlers");
-    network_handler::start_network_handlers(db.clone(), bet_sender.clone()).await;
+    let db_listener = network_handler::start_network_handlers(
+        db.clone(),
+        bet_sender.clone(),
+        ws_data_feed.clone(),
+        rpc_health.clone(),
+        registry_receiver,
+        shutdown_receiver.clone(),
+        db_queue.clone(),
+    )
+    .await;
     tokio::spawn(network_handler::bet_listener(
         db.clone(),
         bet_receiver,
@@ -109,15 +129,62 @@ async fn main() {
             HeaderName::from_static("accept"),
         ]);
 
-    info!("Server started, waiting for CTRL+C");
-    tokio::select! {
-        _ = warp::serve(
-            filters::init_filters(db, ws_data_feed).or(api_doc)
-            .or(swagger_ui).recover(handle_rejection).with(cors),
+    let mut server_shutdown = shutdown_receiver.clone();
+    let (_, server) = warp::serve(
+        filters::init_filters(
+            db,
+            ws_data_feed,
+            rpc_health,
+            registry_sender,
+            shutdown_receiver,
+            db_queue,
         )
-        .run((*config::SERVER_HOST, *config::SERVER_PORT)) => {},
-        _ = signal::ctrl_c() => {
-            warn!("CTRL+C received, stopping process...")
+        .or(api_doc)
+        .or(swagger_ui)
+        .recover(handle_rejection)
+        .with(cors),
+    )
+    .bind_with_graceful_shutdown((*config::SERVER_HOST, *config::SERVER_PORT), async move {
+        shutdown_requested(&mut server_shutdown).await;
+    });
+    let server = tokio::spawn(server);
+
+    info!("Server started, waiting for CTRL+C");
+    shutdown_signal().await;
+    warn!(
+        "Stopping process, waiting up to {:?} for the handlers...",
+        *config::SHUTDOWN_TIMEOUT
+    );
+    shutdown_sender.send_replace(true);
+
+    let drain = async {
+        if let Err(e) = server.await {
+            error!("Server failed {:?}", e);
+        }
+        if let Err(e) = db_listener.await {
+            error!("Db listener failed {:?}", e);
+        }
+    };
+    match timeout(*config::SHUTDOWN_TIMEOUT, drain).await {
+        Ok(()) => info!("Process stopped"),
+        Err(_) => error!("Shutdown timed out, exiting anyway"),
+    }
+}
+
+/// Resolves on CTRL+C or SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate = signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("Error installing the SIGTERM handler");
+        tokio::select! {
+            _ = signal::ctrl_c() => warn!("CTRL+C received"),
+            _ = terminate.recv() => warn!("SIGTERM received"),
         }
     }
+    #[cfg(not(unix))]
+    {
+        let _ = signal::ctrl_c().await;
+        warn!("CTRL+C received");
+    }
 }
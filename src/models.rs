@@ -23,11 +23,12 @@ impl FromStr for LeaderboardType {
 }
 
 pub mod db_models {
+    use super::json_responses::GameResult;
     use super::*;
-    use chrono::serde::ts_seconds;
+    use chrono::serde::{ts_seconds, ts_seconds_option};
     use chrono::{DateTime, Utc};
     use serde_with::{serde_as, DisplayFromStr};
-    use sqlx::types::BigDecimal;
+    use sqlx::types::{BigDecimal, Json};
 
     #[derive(Debug, Clone, ToSchema)]
     #[schema(rename_all = "lowercase")]
@@ -87,6 +88,22 @@ pub mod db_models {
         #[serde(with = "ts_seconds")]
         pub registration_time: DateTime<Utc>,
         pub language: Option<String>,
+        #[serde(skip)]
+        pub token_version: i64,
+    }
+
+    /// Stored refresh token of a partner, the token itself is never stored
+    #[derive(Deserialize, Serialize, Debug, Clone)]
+    pub struct RefreshToken {
+        pub id: i64,
+        pub token_hash: String,
+        /// Tokens rotated from the same login
+        pub family: String,
+        pub partner_id: String,
+        pub expires_at: DateTime<Utc>,
+        pub used: bool,
+        pub revoked: bool,
+        pub created_at: DateTime<Utc>,
     }
 
     #[derive(Deserialize, Serialize, ToSchema, Debug, Clone)]
@@ -107,6 +124,7 @@ pub mod db_models {
     }
 
     #[derive(Serialize, Deserialize, Clone, ToSchema)]
+    #[allow(dead_code)]
     pub struct DepositsSum {
         pub deposits_sum: i64,
     }
@@ -161,6 +179,60 @@ pub mod db_models {
     pub struct LastBlock {
         pub id: i64,
         pub network_id: i64,
+        pub updated_at: DateTime<Utc>,
+    }
+
+    /// Where the prices of the network's tokens come from
+    #[derive(Deserialize, Serialize, ToSchema, Debug, Clone)]
+    pub struct PriceSourceConfig {
+        pub id: i64,
+        pub network_id: i64,
+        /// The source is used only for this token, otherwise for all the network's tokens
+        pub token_address: Option<String>,
+        /// `univ2` or `static`
+        pub kind: String,
+        pub router_address: Option<String>,
+        /// Space separated tokens to route through, the last one is a USD stablecoin
+        pub path: Option<String>,
+        pub quote_decimals: Option<i64>,
+        /// Fixed price of the `static` source
+        pub price: Option<f64>,
+    }
+
+    /// Raw game log which couldn't be turned into a bet
+    #[derive(Deserialize, Serialize, ToSchema, Debug, Clone)]
+    pub struct LogDeadLetter {
+        pub id: i64,
+        pub network_id: i64,
+        pub block_number: i64,
+        pub block_hash: String,
+        pub transaction_hash: String,
+        pub log_index: i64,
+        pub address: String,
+        /// Hex encoded topics, the first one is the event signature
+        pub topics: Vec<String>,
+        /// Hex encoded non indexed event fields
+        pub data: String,
+        pub reason: String,
+        /// UNIX timestamp of the log's block, unknown if the log has no block number
+        #[serde(with = "ts_seconds_option")]
+        #[schema(value_type = Option<i64>)]
+        pub block_timestamp: Option<DateTime<Utc>>,
+        #[serde(with = "ts_seconds")]
+        #[schema(value_type = i64)]
+        pub timestamp: DateTime<Utc>,
+    }
+
+    #[derive(Deserialize, Serialize, ToSchema, Debug)]
+    pub struct BackfillJob {
+        pub id: i64,
+        pub network_id: i64,
+        pub game_id: Option<i64>,
+        pub from_block: i64,
+        pub to_block: i64,
+        /// The last block backfilled so far
+        pub processed_block: Option<i64>,
+        pub done: bool,
     }
 
     #[derive(Deserialize, Serialize, ToSchema, Debug)]
@@ -170,6 +242,23 @@ pub mod db_models {
         pub price: f64,
     }
 
+    #[derive(Deserialize, Serialize, ToSchema, Debug)]
+    pub struct TokenPriceState {
+        pub price: Option<f64>,
+        pub updated_at: DateTime<Utc>,
+        /// The price wasn't updated for too long
+        pub stale: bool,
+    }
+
+    #[derive(Deserialize, Serialize, ToSchema, Debug)]
+    pub struct TokenPricePoint {
+        pub price: f64,
+        /// The sample deviated too much from the previous one and was discarded
+        pub anomalous: bool,
+        #[serde(with = "ts_seconds")]
+        pub timestamp: DateTime<Utc>,
+    }
+
     #[derive(Deserialize, Serialize, ToSchema, Debug)]
     pub struct PlayerTotals {
         pub bets_amount: i64,
@@ -200,6 +289,7 @@ pub mod db_models {
     }
 
     #[derive(Deserialize, Serialize, ToSchema)]
+    #[allow(dead_code)]
     pub struct NativeCurrency {
         pub id: i64,
         pub name: String,
@@ -208,6 +298,7 @@ pub mod db_models {
     }
 
     #[derive(Deserialize, Serialize, ToSchema)]
+    #[allow(dead_code)]
     pub struct Network {
         pub id: i64,
         pub name: String,
@@ -223,6 +314,8 @@ pub mod db_models {
         pub currency_name: String,
         pub currency_symbol: String,
         pub decimals: i64,
+        /// Amount of blocks on top of the bet's block before it's persisted
+        pub confirmations: i64,
     }
 
     #[derive(Deserialize, Serialize, ToSchema)]
@@ -246,6 +339,7 @@ pub mod db_models {
         pub name: String,
         /// 42 symbols
         pub contract_address: String,
+        pub decimals: i64,
     }
 
     #[derive(Deserialize, Serialize, ToSchema)]
@@ -256,9 +350,11 @@ pub mod db_models {
         /// 42 symbols
         pub address: String,
         pub result_event_signature: String,
+        /// Units of the event's `multiplier` field per 1.0x
+        pub multiplier_precision: i64,
     }
 
-    #[derive(Deserialize, Serialize, ToSchema)]
+    #[derive(Deserialize, Serialize, ToSchema, Clone)]
     pub struct GameInfo {
         pub id: i64,
         pub network_id: i64,
@@ -268,6 +364,12 @@ pub mod db_models {
         pub event_signature: String,
         pub event_types: String,
         pub event_names: String,
+        /// Declaration of the event like `Name(address,uint256)`
+        pub event: Option<String>,
+        /// Solidity JSON abi of the event
+        pub event_abi: Option<String>,
+        /// Units of the event's `multiplier` field per 1.0x
+        pub multiplier_precision: i64,
     }
 
     #[derive(Deserialize, Serialize, ToSchema)]
@@ -297,6 +399,7 @@ pub mod db_models {
     }
 
     #[derive(Deserialize, Serialize, Default, ToSchema)]
+    #[allow(dead_code)]
     pub struct PartnerCredentials {
         pub id: i64,
         pub login: String,
@@ -321,7 +424,18 @@ pub mod db_models {
         pub multiplier: f64,
         #[serde_as(as = "DisplayFromStr")]
         pub profit: BigDecimal,
-        //pub player_hand: Option<[Card; 5]>,
+        pub block_number: i64,
+        pub block_hash: String,
+        /// Position of the event log in the block
+        pub log_index: i64,
+        pub game_result: Option<GameResult>,
+    }
+
+    #[derive(Deserialize, Serialize, Clone, Debug)]
+    pub struct BetBlock {
+        pub block_number: i64,
+        /// 66 symbols
+        pub block_hash: String,
     }
 
     #[serde_as]
@@ -345,7 +459,8 @@ pub mod db_models {
         pub multiplier: f64,
         #[serde_as(as = "DisplayFromStr")]
         pub profit: BigDecimal,
-        //pub player_hand: Option<[Card; 5]>,
+        #[schema(value_type = Option<GameResult>)]
+        pub game_result: Option<Json<GameResult>>,
     }
 
     #[derive(Deserialize, Serialize, Clone, Debug, ToSchema)]
@@ -353,6 +468,10 @@ pub mod db_models {
         pub signature: String,
         pub types: String,
         pub names: String,
+        /// Declaration of the event like `Name(address,uint256)`
+        pub event: Option<String>,
+        /// Solidity JSON abi of the event, used instead of the types and names
+        pub abi: Option<String>,
     }
 
     // pub struct Lobby {
@@ -372,14 +491,15 @@ pub mod json_responses {
 
     use super::db_models::{
         AmountConnectedWallets, Bet, BetInfo, BlockExplorerUrl, Game, GameAbi, Leaderboard,
-        NetworkInfo, Nickname, Partner, PartnerContact, PartnerSite, Player, PlayerTotals,
-        PlayersTotals, RefClicks, RpcUrl, SiteSubId, Token, Totals, Withdrawal,
+        LogDeadLetter, NetworkInfo, Nickname, Partner, PartnerContact, PartnerSite, Player,
+        PlayerTotals, PlayersTotals, RefClicks, RpcUrl, SiteSubId, Token, TokenPricePoint, Totals,
+        Withdrawal,
     };
     use super::*;
-    use chrono::serde::ts_seconds;
+    use chrono::serde::{ts_seconds, ts_seconds_option};
     use chrono::{DateTime, Utc};
     use serde_with::{serde_as, DisplayFromStr};
-    use sqlx::types::BigDecimal;
+    use sqlx::types::{BigDecimal, Json};
 
     #[derive(Serialize, Deserialize, ToSchema)]
     pub enum Status {
@@ -388,6 +508,7 @@ pub mod json_responses {
     }
 
     #[derive(Serialize, Deserialize)]
+    #[allow(dead_code)]
     pub struct TextResponse {
         // OK/ERR
         //#[schema(example = "OK")]
@@ -408,8 +529,15 @@ pub mod json_responses {
     pub enum ResponseBody {
         ErrorText(ErrorText),
         InfoText(InfoText),
+        CreatedId(CreatedId),
+        LogDeadLetters(LogDeadLetters),
+        DeadLettersReprocessed(DeadLettersReprocessed),
+        MultipliersRecomputed(MultipliersRecomputed),
         Networks(Networks),
+        NetworkSync(NetworkSync),
+        DbQueue(DbQueue),
         Rpcs(Rpcs),
+        RpcsHealth(RpcsHealth),
         BlockExplorers(BlockExplorers),
         Tokens(Tokens),
         Game(Game),
@@ -421,6 +549,7 @@ pub mod json_responses {
         LatestGames(LatestGames),
         PlayerTotals(PlayerTotals),
         TokenPrice(TokenPrice),
+        TokenPriceHistory(TokenPriceHistory),
         PartnerInfo(PartnerInfo),
         PartnerContacts(Vec<PartnerContact>),
         PartnerSiteInfo(Vec<PartnerSiteInfo>),
@@ -431,6 +560,8 @@ pub mod json_responses {
         AmountClicksTimeMapped(ClicksTimeMapped),
         ConnectedWallets(Vec<ConnectedWalletInfo>),
         AccessToken(AccessToken),
+        SiweNonce(SiweNonce),
+        PlayerSession(PlayerSession),
         PlayersTotals(PlayersTotals),
         Withdrawals(Vec<Withdrawal>),
     }
@@ -470,6 +601,22 @@ pub mod json_responses {
         pub refresh_token: String,
     }
 
+    #[derive(Serialize, Deserialize, Clone, ToSchema)]
+    pub struct SiweNonce {
+        pub nonce: String,
+        #[serde(with = "ts_seconds")]
+        #[schema(value_type = i64)]
+        pub expires_at: DateTime<Utc>,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, ToSchema)]
+    pub struct PlayerSession {
+        pub wallet: String,
+        pub access_token: String,
+        pub token_type: String,
+        pub expires_in: usize,
+    }
+
     #[derive(Serialize, Deserialize, Clone, ToSchema)]
     pub struct PartnerInfo {
         pub basic: Partner,
@@ -493,6 +640,30 @@ pub mod json_responses {
         pub message: String,
     }
 
+    #[derive(Serialize, Deserialize, Clone, ToSchema)]
+    pub struct CreatedId {
+        pub id: i64,
+    }
+
+    #[derive(Deserialize, Serialize, ToSchema)]
+    pub struct LogDeadLetters {
+        pub dead_letters: Vec<LogDeadLetter>,
+    }
+
+    #[derive(Deserialize, Serialize, ToSchema)]
+    pub struct DeadLettersReprocessed {
+        /// Logs turned into bets or recognized as not finished games, removed from the dead letters
+        pub recovered: u64,
+        /// Logs which still fail, their reason is updated
+        pub failed: u64,
+    }
+
+    #[derive(Deserialize, Serialize, ToSchema)]
+    pub struct MultipliersRecomputed {
+        /// Bets stored before the multipliers were computed, which got their multiplier
+        pub bets: u64,
+    }
+
     #[derive(Deserialize, Serialize, ToSchema)]
     pub struct LatestGames {
         pub games: Vec<String>,
@@ -501,6 +672,17 @@ pub mod json_responses {
     #[derive(Deserialize, Serialize, ToSchema)]
     pub struct TokenPrice {
         pub token_price: f64,
+        /// UNIX timestamp of the last accepted price
+        #[serde(with = "ts_seconds_option")]
+        #[schema(value_type = Option<i64>)]
+        pub updated_at: Option<DateTime<Utc>>,
+        /// The price is outdated and excluded from the totals and leaderboards
+        pub stale: bool,
+    }
+
+    #[derive(Deserialize, Serialize, ToSchema)]
+    pub struct TokenPriceHistory {
+        pub prices: Vec<TokenPricePoint>,
     }
 
     #[derive(Deserialize, Serialize, ToSchema)]
@@ -515,11 +697,64 @@ pub mod json_responses {
         pub networks: Vec<NetworkFullInfo>,
     }
 
+    /// Queue of the db writer, a full queue pauses the networks polling
+    #[derive(Deserialize, Serialize, ToSchema)]
+    pub struct DbQueue {
+        /// Messages waiting to be written
+        pub depth: usize,
+        pub capacity: usize,
+        /// Times the handlers waited for room in the queue
+        pub stalls: u64,
+        /// Failed writes that were attempted again
+        pub retries: u64,
+        /// Bets which couldn't be stored and were moved to the dead letters
+        pub dead_letters: u64,
+    }
+
+    /// Indexing progress of a network
+    #[derive(Deserialize, Serialize, ToSchema)]
+    pub struct NetworkSync {
+        pub network_id: i64,
+        /// Latest block reported by the rpcs, unknown until they're polled
+        pub head_block: Option<u64>,
+        /// Latest block which logs are processed
+        pub indexed_block: Option<u64>,
+        /// Blocks the indexer is behind the head
+        pub lag: Option<u64>,
+        /// UNIX timestamp of the last cursor update
+        #[serde(with = "ts_seconds_option")]
+        #[schema(value_type = Option<i64>)]
+        pub indexed_at: Option<DateTime<Utc>>,
+    }
+
     #[derive(Deserialize, Serialize, ToSchema)]
     pub struct Rpcs {
         pub rpcs: Vec<RpcUrl>,
     }
 
+    #[derive(Deserialize, Serialize, ToSchema, Clone)]
+    pub struct RpcHealth {
+        pub url: String,
+        /// Moving average of the request latency
+        pub latency_ms: f64,
+        /// Moving average of the failed requests share
+        pub error_rate: f64,
+        pub requests: u64,
+        pub errors: u64,
+        pub block_number: Option<u64>,
+        /// Blocks behind the most up to date rpc of the network
+        pub block_lag: u64,
+        /// Time left until the rpc is used again
+        pub backoff_ms: u64,
+        /// The rpc with the lowest score is preferred
+        pub score: f64,
+    }
+
+    #[derive(Deserialize, Serialize, ToSchema)]
+    pub struct RpcsHealth {
+        pub rpcs: Vec<RpcHealth>,
+    }
+
     #[derive(Deserialize, Serialize, ToSchema)]
     pub struct BlockExplorers {
         pub explorers: Vec<BlockExplorerUrl>,
@@ -531,12 +766,32 @@ pub mod json_responses {
     }
 
     #[serde_as]
-    #[derive(Deserialize, Serialize, Clone, Debug)]
+    #[derive(Deserialize, Serialize, ToSchema, Clone, Debug)]
     pub struct Card {
         pub number: u8,
         pub suit: u8,
     }
 
+    /// Game specific outcome of a bet, only the fields emitted by the game are set
+    #[derive(Deserialize, Serialize, ToSchema, Clone, Debug, Default)]
+    pub struct GameResult {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub player_hand: Option<Vec<Card>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub coin_outcomes: Option<Vec<u64>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub dice_outcomes: Option<Vec<u64>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub outcomes: Option<Vec<u64>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub random_actions: Option<Vec<u64>>,
+        /// Payout of every game in the bet, in wei
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub payouts: Option<Vec<String>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub outcome: Option<u64>,
+    }
+
     #[serde_as]
     #[derive(Deserialize, Serialize, ToSchema, Debug, Clone)]
     pub struct BetInfoResponse {
@@ -559,6 +814,9 @@ pub mod json_responses {
         #[serde_as(as = "DisplayFromStr")]
         pub profit: BigDecimal,
         pub player_hand: Option<Vec<Card>>,
+        pub game_result: Option<GameResult>,
+        /// The bet has enough confirmations and was persisted
+        pub confirmed: bool,
     }
 
     impl From<BetInfo> for BetInfoResponse {
@@ -579,7 +837,12 @@ pub mod json_responses {
                 bets: value.bets,
                 multiplier: value.multiplier,
                 profit: value.profit,
-                player_hand: None,
+                player_hand: value
+                    .game_result
+                    .as_ref()
+                    .and_then(|result| result.player_hand.clone()),
+                game_result: value.game_result.map(|result| result.0),
+                confirmed: true,
             }
         }
     }
@@ -602,6 +865,7 @@ pub mod json_responses {
                 bets: value.bets,
                 multiplier: value.multiplier,
                 profit: value.profit,
+                game_result: value.game_result.map(Json),
             }
         }
     }
@@ -624,7 +888,12 @@ pub mod json_responses {
                 bets: value.bets,
                 multiplier: value.multiplier,
                 profit: value.profit,
-                player_hand: None,
+                player_hand: value
+                    .game_result
+                    .as_ref()
+                    .and_then(|result| result.player_hand.clone()),
+                game_result: value.game_result,
+                confirmed: true,
             }
         }
     }
@@ -642,6 +911,10 @@ pub mod json_responses {
                 bets: value.bets,
                 multiplier: value.multiplier,
                 profit: value.profit,
+                block_number: Default::default(),
+                block_hash: Default::default(),
+                log_index: Default::default(),
+                game_result: value.game_result,
             }
         }
     }
@@ -670,6 +943,22 @@ pub mod json_responses {
     pub struct Bets {
         pub bets: Vec<BetInfo>,
     }
+
+    #[derive(Deserialize, Serialize, Debug, Clone)]
+    #[serde(tag = "type")]
+    pub enum WsDataFeedMessage {
+        Bet(BetInfoResponse),
+        /// The bet was removed by a chain reorganization
+        BetReverted(BetInfoResponse),
+    }
+
+    impl WsDataFeedMessage {
+        pub fn game_name(&self) -> &str {
+            match self {
+                Self::Bet(bet) | Self::BetReverted(bet) => &bet.game_name,
+            }
+        }
+    }
 }
 
 pub mod json_requests {
@@ -679,6 +968,11 @@ pub mod json_requests {
     pub struct SetNickname {
         pub address: String,
         pub nickname: String,
+        /// 8 to 64 letters or digits, unique per address, can't be used twice
+        pub nonce: String,
+        /// UNIX timestamp of the signature
+        pub timestamp: u64,
+        /// Signature of `{nickname} {nonce} {timestamp}`
         pub signature: String,
     }
 
@@ -688,6 +982,24 @@ pub mod json_requests {
         pub password: String,
     }
 
+    #[derive(Deserialize, Serialize, ToSchema)]
+    pub struct SiweLogin {
+        /// EIP-4361 message with a nonce from `/api/auth/nonce`
+        pub message: String,
+        /// Hex encoded `personal_sign` signature of the message
+        pub signature: String,
+    }
+
+    #[derive(Deserialize, Serialize, ToSchema)]
+    pub struct SessionNickname {
+        pub nickname: String,
+    }
+
+    #[derive(Deserialize, Serialize, ToSchema)]
+    pub struct RefreshTokenRequest {
+        pub refresh_token: String,
+    }
+
     #[derive(Deserialize, Serialize, ToSchema)]
     pub struct SubmitError {
         pub error: String,
@@ -697,6 +1009,11 @@ pub mod json_requests {
     pub struct CreateReferal {
         pub refer_to: String,
         pub referal: String,
+        /// 8 to 64 letters or digits, unique per referal, can't be used twice
+        pub nonce: String,
+        /// UNIX timestamp of the signature
+        pub timestamp: u64,
+        /// Signature of `{refer_to} {referal} {nonce} {timestamp}`
         pub signature: String,
     }
 
@@ -763,6 +1080,11 @@ pub mod json_requests {
         pub user_wallet: String,
         pub site_id: i64,
         pub sub_id: i64,
+        /// 8 to 64 letters or digits, unique per user wallet, can't be used twice
+        pub nonce: String,
+        /// UNIX timestamp of the signature
+        pub timestamp: u64,
+        /// Signature of `CONNECT WALLET {partner_wallet} {user_wallet} {site_id} {sub_id} {nonce} {timestamp}`
         pub signature: String,
     }
 
@@ -786,4 +1108,31 @@ pub mod json_requests {
         pub email: String,
         pub message: String,
     }
+
+    /// Rpc or block explorer url of the network
+    #[derive(Deserialize, Serialize, ToSchema)]
+    pub struct NetworkUrl {
+        pub network_id: i64,
+        pub url: String,
+    }
+
+    #[derive(Deserialize, Serialize, ToSchema)]
+    pub struct NewToken {
+        pub network_id: i64,
+        pub name: String,
+        /// 42 symbols
+        pub contract_address: String,
+        pub decimals: i64,
+    }
+
+    #[derive(Deserialize, Serialize, ToSchema)]
+    pub struct NewGame {
+        pub network_id: i64,
+        pub name: String,
+        /// 42 symbols
+        pub address: String,
+        pub result_event_signature: String,
+        /// Units of the event's `multiplier` field per 1.0x, 10000 for basis points
+        pub multiplier_precision: i64,
+    }
 }
@@ -1,332 +1,973 @@
-use crate::models::db_models::{GameInfo, NetworkInfo, Token, TokenPrice};
-use crate::models::json_responses::{BetInfoResponse, Card};
+use crate::config::{
+    BACKFILL_DELAY, BACKFILL_WINDOW, DB_QUEUE_SIZE, DB_RETRIES, DB_RETRY_DELAY, MAX_PRICE_DEVIATION,
+};
+use crate::game_abi::{parse_address, parse_signature, validate_game, EventDecoder};
+use crate::models::db_models::{
+    BackfillJob, Bet, BetBlock, GameInfo, LogDeadLetter, NetworkInfo, Token, TokenPrice,
+};
+use crate::models::json_responses::{BetInfoResponse, Card, GameResult, WsDataFeedMessage};
+use crate::price_oracle::{token_price_source, PriceSource};
+use crate::rpc_pool::{RpcHealthRegistry, RpcPool, SharedRpcPool};
 use crate::{communication::*, db::DB};
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use ethabi::ethereum_types::{H256, U256};
-use ethabi::{ParamType, Token as EthToken};
+use ethabi::Token as EthToken;
+use futures::future::join_all;
 use rust_decimal::prelude::ToPrimitive;
-use rust_decimal::Decimal;
 use sqlx::types::BigDecimal;
-use std::collections::HashMap;
-use std::fs::File;
-use tokio::time::{sleep, Duration};
-use web3::contract::Contract;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Duration, Instant};
 
 use std::str::FromStr;
 
-use tracing::{debug, error, warn};
+use tracing::{debug, error, info, warn};
 
-use web3::types::{FilterBuilder, Log, H160};
+use thiserror::Error;
+use web3::types::{Block, BlockId, BlockNumber, Bytes, FilterBuilder, Log, H160};
 
-type GameInnerInfo = HashMap<H256, (H160, (Vec<ParamType>, Vec<String>), GameInfo)>;
+type GameInnerInfo = HashMap<H256, (H160, EventDecoder, GameInfo)>;
 
-pub async fn start_network_handlers(db: DB, bet_sender: BetSender) {
+/// Delay between the token price updates
+const PRICE_UPDATE_INTERVAL: Duration = Duration::from_secs(180);
+
+/// Delay between the checks for new backfill jobs
+const BACKFILL_POLL: Duration = Duration::from_secs(30);
+
+/// Delay between the reloads of the network's games
+const GAMES_REFRESH: Duration = Duration::from_secs(60);
+
+/// Amount of the latest seen blocks per network, which hashes are checked for reorgs
+const TRACKED_BLOCKS: usize = 128;
+
+/// Finished bet waiting for the network's confirmation depth
+struct PendingBet {
+    bet: DbPropagatedBet,
+    propagated: PropagatedBet,
+}
+
+/// Hashes of the recently processed blocks of a network, in ascending order
+#[derive(Default)]
+pub struct BlockTracker {
+    blocks: VecDeque<(u64, H256)>,
+}
+
+impl BlockTracker {
+    fn from_bet_blocks(bet_blocks: Vec<BetBlock>) -> Self {
+        let mut tracker = Self::default();
+        for block in bet_blocks.into_iter().rev() {
+            let mut hash: [u8; 32] = [0; 32];
+            if hex::decode_to_slice(&block.block_hash.as_bytes()[2..], &mut hash).is_err() {
+                warn!("Malformed block hash `{}`", block.block_hash);
+                continue;
+            }
+            tracker.track(block.block_number as u64, H256::from(hash));
+        }
+        tracker
+    }
+
+    fn track(&mut self, number: u64, hash: H256) {
+        if let Some((last, _)) = self.blocks.back() {
+            if *last >= number {
+                return;
+            }
+        }
+        self.blocks.push_back((number, hash));
+        if self.blocks.len() > TRACKED_BLOCKS {
+            self.blocks.pop_front();
+        }
+    }
+
+    /// Forgets all the blocks after `number`
+    fn rewind(&mut self, number: u64) {
+        while let Some((last, _)) = self.blocks.back() {
+            if *last <= number {
+                break;
+            }
+            self.blocks.pop_back();
+        }
+    }
+
+    /// Compares tracked hashes with the chain, returns the common ancestor
+    /// if the latest tracked block is not canonical anymore
+    async fn find_reorg<T: web3::Transport>(
+        &self,
+        web3: &web3::Web3<T>,
+    ) -> Result<Option<u64>, web3::Error> {
+        for (depth, (number, hash)) in self.blocks.iter().rev().enumerate() {
+            let block = match web3
+                .eth()
+                .block(BlockId::Number(BlockNumber::Number((*number).into())))
+                .await?
+            {
+                Some(block) => block,
+                // the node is behind us, check again later
+                None => return Ok(None),
+            };
+            if block.hash == Some(*hash) {
+                return Ok(if depth == 0 { None } else { Some(*number) });
+            }
+        }
+
+        Ok(self
+            .blocks
+            .front()
+            .map(|(number, _)| number.saturating_sub(1)))
+    }
+}
+
+/// Loads the games of the network with their event layouts,
+/// the invalid games are reported and skipped
+async fn load_games(db: &DB, network_id: i64) -> Result<GameInnerInfo, sqlx::Error> {
+    let games = db
+        .query_all_games_infos(network_id)
+        .await?
+        .into_iter()
+        .filter_map(|game| {
+            debug!("Producing data for game `{:?}`", game.id);
+            let (address, layout) = match validate_game(&game) {
+                Ok(game) => game,
+                Err(e) => {
+                    error!(
+                        "Network {} skipping the game {} `{}`: {}",
+                        network_id, game.id, game.name, e
+                    );
+                    return None;
+                }
+            };
+            Some((layout.signature, (address, layout.decoder, game)))
+        })
+        .collect();
+
+    Ok(games)
+}
+
+/// Contract addresses and event signatures present only in the first games
+fn games_difference(games: &GameInnerInfo, other: &GameInnerInfo) -> Vec<(H160, H256)> {
+    games
+        .iter()
+        .filter(|(signature, (address, _, _))| {
+            !matches!(other.get(signature), Some((other_address, _, _)) if other_address == address)
+        })
+        .map(|(signature, (address, _, _))| (*address, *signature))
+        .collect()
+}
+
+/// Games of a network, reloaded on the registry changes and periodically,
+/// to also pick up the games changed directly in the db
+pub struct GameRegistry {
+    network_id: i64,
+    db: DB,
+    games: GameInnerInfo,
+    changed: watch::Receiver<()>,
+    loaded_at: Instant,
+}
+
+impl GameRegistry {
+    async fn load(
+        network_id: i64,
+        db: DB,
+        changed: watch::Receiver<()>,
+    ) -> Result<Self, sqlx::Error> {
+        let games = load_games(&db, network_id).await?;
+        Ok(Self {
+            network_id,
+            db,
+            games,
+            changed,
+            loaded_at: Instant::now(),
+        })
+    }
+
+    fn games(&self) -> &GameInnerInfo {
+        &self.games
+    }
+
+    /// Reloads the games if they were changed or weren't reloaded for a while,
+    /// the current games are kept on errors
+    async fn refresh(&mut self) {
+        let changed = self.changed.has_changed().unwrap_or(false);
+        if !changed && self.loaded_at.elapsed() < GAMES_REFRESH {
+            return;
+        }
+        self.changed.borrow_and_update();
+        self.loaded_at = Instant::now();
+
+        let games = match load_games(&self.db, self.network_id).await {
+            Ok(games) => games,
+            Err(e) => {
+                error!("Network {} error reloading games {:?}", self.network_id, e);
+                return;
+            }
+        };
+        let added = games_difference(&games, &self.games);
+        let removed = games_difference(&self.games, &games);
+        if !added.is_empty() || !removed.is_empty() {
+            info!(
+                "Network {} games reloaded, added {:?}, removed {:?}",
+                self.network_id, added, removed
+            );
+        }
+        self.games = games;
+    }
+}
+
+/// Sleeps for the duration unless the shutdown is requested in the meantime
+async fn sleep_or_shutdown(shutdown: &mut ShutdownReceiver, duration: Duration) {
+    tokio::select! {
+        _ = sleep(duration) => {}
+        _ = shutdown_requested(shutdown) => {}
+    }
+}
+
+/// Handlers of a network, aborted when dropped
+struct NetworkTasks {
+    /// Persists its block cursor before exiting on shutdown
+    live: Option<JoinHandle<()>>,
+    background: Vec<JoinHandle<()>>,
+    /// Makes the live handler reload its games
+    games_changed: watch::Sender<()>,
+}
+
+impl NetworkTasks {
+    /// Aborts the background handlers and waits for the live one to stop
+    async fn shutdown(mut self) {
+        for task in self.background.drain(..) {
+            task.abort();
+        }
+        if let Some(live) = self.live.take() {
+            if let Err(e) = live.await {
+                error!("Network handler failed {:?}", e);
+            }
+        }
+    }
+}
+
+impl Drop for NetworkTasks {
+    fn drop(&mut self) {
+        for task in self.live.iter().chain(self.background.iter()) {
+            task.abort();
+        }
+    }
+}
+
+/// Shared state the handlers of every network are spawned with
+#[derive(Clone)]
+struct HandlersContext {
+    db: DB,
+    db_sender: DbSender,
+    bet_sender: BetSender,
+    rpc_health: RpcHealthRegistry,
+    shutdown: ShutdownReceiver,
+}
+
+/// Spawns the handlers of every network, returns the db listener
+/// which finishes once the handlers are stopped and its messages are written
+pub async fn start_network_handlers(
+    db: DB,
+    bet_sender: BetSender,
+    ws_data_feed: WsDataFeedSender,
+    rpc_health: RpcHealthRegistry,
+    registry_receiver: RegistryReceiver,
+    shutdown: ShutdownReceiver,
+    db_queue: SharedDbQueueMetrics,
+) -> JoinHandle<()> {
     // channels
-    let (db_sender, db_receiver) = unbounded_channel();
+    let (db_sender, db_receiver) = db_channel(*DB_QUEUE_SIZE, db_queue.clone());
 
     // spawn db listener
     // TODO: make a proper db listener
-    tokio::spawn(db_listener(db_receiver, db.clone()));
+    let db_listener = tokio::spawn(db_listener(db_receiver, db.clone(), ws_data_feed, db_queue));
 
+    let context = HandlersContext {
+        db: db.clone(),
+        db_sender,
+        bet_sender,
+        rpc_health,
+        shutdown,
+    };
+
+    let mut handlers = HashMap::new();
     let networks = db.query_all_networks().await.unwrap();
-    for network in networks.iter() {
-        debug!(
-            "Staring games handlers on network: `{:?}`",
-            network.network_id
-        );
-        let rpcs: Vec<String> = db
-            .query_all_rpcs(network.network_id)
-            .await
-            .unwrap()
-            .into_iter()
-            .map(|rpc| rpc.url)
-            .collect();
-        if rpcs.is_empty() {
-            continue;
+    for network in networks.into_iter() {
+        let network_id = network.network_id;
+        match spawn_network_handlers(network, &context).await {
+            Ok(Some(tasks)) => {
+                handlers.insert(network_id, tasks);
+            }
+            Ok(None) => {}
+            Err(e) => error!("Error starting network {} handlers {:?}", network_id, e),
         }
-        let games: GameInnerInfo = db
-            .query_all_games_infos(network.network_id)
-            .await
-            .unwrap()
+    }
+
+    tokio::spawn(registry_listener(registry_receiver, handlers, context));
+
+    db_listener
+}
+
+/// Restarts the handlers of the networks changed through the admin api,
+/// makes them reload the changed games, stops them on shutdown
+async fn registry_listener(
+    mut receiver: RegistryReceiver,
+    mut handlers: HashMap<i64, NetworkTasks>,
+    context: HandlersContext,
+) {
+    let mut shutdown = context.shutdown.clone();
+    loop {
+        let change = tokio::select! {
+            change = receiver.recv() => change,
+            _ = shutdown_requested(&mut shutdown) => None,
+        };
+        let Some(change) = change else {
+            break;
+        };
+        // an admin request may touch several entries at once, restart only once
+        let mut changes = HashSet::from([change]);
+        while let Ok(change) = receiver.try_recv() {
+            changes.insert(change);
+        }
+
+        for change in changes {
+            let network_id = match change {
+                RegistryChange::Network(network_id) => network_id,
+                RegistryChange::Games(network_id) => {
+                    match handlers.get(&network_id) {
+                        Some(tasks) => {
+                            debug!("Network {} games changed", network_id);
+                            tasks.games_changed.send_replace(());
+                        }
+                        None => debug!("Network {} isn't running", network_id),
+                    }
+                    continue;
+                }
+            };
+            info!(
+                "Network {} configuration changed, restarting it",
+                network_id
+            );
+            handlers.remove(&network_id);
+            context.rpc_health.write().await.remove(&network_id);
+
+            let network = match context.db.query_network(network_id).await {
+                Ok(Some(network)) => network,
+                Ok(None) => {
+                    info!("Network {} was removed", network_id);
+                    continue;
+                }
+                Err(e) => {
+                    error!("Error getting network {} {:?}", network_id, e);
+                    continue;
+                }
+            };
+            match spawn_network_handlers(network, &context).await {
+                Ok(Some(tasks)) => {
+                    handlers.insert(network_id, tasks);
+                }
+                Ok(None) => {}
+                Err(e) => error!("Error restarting network {} handlers {:?}", network_id, e),
+            }
+        }
+    }
+
+    info!("Stopping {} network handlers", handlers.len());
+    join_all(handlers.into_values().map(NetworkTasks::shutdown)).await;
+    // dropping the context closes the db channel once the handlers are gone
+}
+
+/// Spawns the live, backfill and price handlers of the network,
+/// `None` if the network has no usable rpcs
+async fn spawn_network_handlers(
+    network: NetworkInfo,
+    context: &HandlersContext,
+) -> Result<Option<NetworkTasks>, sqlx::Error> {
+    let db = &context.db;
+    debug!(
+        "Staring games handlers on network: `{:?}`",
+        network.network_id
+    );
+    let rpcs: Vec<String> = db
+        .query_all_rpcs(network.network_id)
+        .await?
+        .into_iter()
+        .map(|rpc| rpc.url)
+        .collect();
+    let rpcs = match RpcPool::new(network.network_id, &rpcs, context.rpc_health.clone()) {
+        Some(rpcs) => rpcs,
+        None => {
+            warn!("Network {} has no usable rpcs", network.network_id);
+            return Ok(None);
+        }
+    };
+    let (games_changed, games_receiver) = watch::channel(());
+    let games = GameRegistry::load(network.network_id, db.clone(), games_receiver).await?;
+
+    let last_block = db
+        .query_last_block(network.network_id)
+        .await?
+        .map(|block| block.id as u64);
+
+    let blocks = BlockTracker::from_bet_blocks(
+        db.query_recent_bet_blocks(network.network_id, TRACKED_BLOCKS as i64)
+            .await?,
+    );
+
+    let rpcs = Arc::new(Mutex::new(rpcs));
+    let mut background = Vec::new();
+
+    background.push(tokio::spawn(backfill_handler(
+        network.clone(),
+        rpcs.clone(),
+        db.clone(),
+        context.shutdown.clone(),
+    )));
+
+    let price_sources = db.query_price_sources(network.network_id).await?;
+    if !price_sources.is_empty() {
+        let tokens = match db.get_unique_tokens(network.network_id).await {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                error!("Error getting list of tokens {:?}", e);
+                return Err(e);
+            }
+        };
+        let tokens: Vec<(Token, Box<dyn PriceSource>)> = tokens
             .into_iter()
-            .map(|game| {
-                debug!("Producing data for game `{:?}`", game.id);
-                let mut game_address: [u8; 20] = [0; 20];
-                hex::decode_to_slice(game.address[2..].as_bytes(), &mut game_address).unwrap();
-
-                let mut event_signature: [u8; 32] = [0; 32];
-                hex::decode_to_slice(game.event_signature[2..].as_bytes(), &mut event_signature)
-                    .unwrap();
-                (
-                    H256::from_slice(&event_signature),
-                    (
-                        H160::from_slice(&game_address),
-                        (
-                            serde_json::from_str::<Vec<ParamType>>(&game.event_types).unwrap(),
-                            game.event_names
-                                .split(' ')
-                                .map(|s| s.to_string())
-                                .collect::<Vec<String>>(),
-                        ),
-                        game,
-                    ),
-                )
+            .filter_map(|token| {
+                let source = token_price_source(&token, &price_sources, &rpcs)?;
+                Some((token, source))
             })
             .collect();
 
-        let last_block = db
-            .query_last_block(network.network_id)
-            .await
-            .unwrap()
-            .map(|block| block.id as u64);
-
-        tokio::spawn(network_handler(
+        background.push(tokio::spawn(token_price_handler(
             network.clone(),
-            rpcs,
-            games,
-            db_sender.clone(),
-            bet_sender.clone(),
-            last_block,
-        ));
+            tokens,
+            context.db_sender.clone(),
+        )));
     }
 
-    let tokens = db
-        .get_unique_tokens(56)
-        .await
-        .map_err(|e| {
-            error!("Error getting list of tokens {:?}", e);
-            e
-        })
-        .unwrap();
-
-    tokio::spawn(token_price_handler(
-        networks[0].clone(),
-        vec!["https://bsc-dataseed1.binance.org".into()],
-        tokens,
-        db_sender,
-        "0x55d398326f99059fF775485246999027B3197955".into(),
-        "0x10ED43C718714eb63d5aA57B78B54704E256024E".into(),
+    let live = tokio::spawn(network_handler(
+        network,
+        rpcs,
+        games,
+        context.db_sender.clone(),
+        context.bet_sender.clone(),
+        last_block,
+        blocks,
+        context.shutdown.clone(),
     ));
+
+    Ok(Some(NetworkTasks {
+        live: Some(live),
+        background,
+        games_changed,
+    }))
 }
-async fn handle_game_log(
-    log: Log,
+
+/// Reason the game log couldn't be turned into a bet
+#[derive(Error, Debug)]
+pub enum LogError {
+    #[error("The log has no topics")]
+    NoTopics,
+
+    #[error("No game with the event signature `{0:?}`")]
+    UnknownSignature(H256),
+
+    #[error("Error decoding the event: {0}")]
+    Decode(ethabi::Error),
+
+    #[error("Field `{0}` is missing or malformed")]
+    BadField(&'static str),
+}
+
+/// Decodes the game event, returns the bet, its game and whether the game is finished,
+/// the bet is placed at the `timestamp` of its block
+fn decode_game_log<'a>(
+    log: &Log,
     network: &NetworkInfo,
-    games: &GameInnerInfo,
-    db_sender: &DbSender,
-    bet_sender: &BetSender,
-    block_id: u64,
-) {
+    games: &'a GameInnerInfo,
+    timestamp: DateTime<Utc>,
+) -> Result<(BetInfoResponse, &'a GameInfo, bool), LogError> {
     debug!("Log received {:?}", log);
 
-    let topics = log.topics;
-    let (_, (types, names), game) = match games.get(&topics[0]) {
-        Some(r) => r,
+    let signature = log.topics.first().ok_or(LogError::NoTopics)?;
+    let (_, decoder, game) = games
+        .get(signature)
+        .ok_or(LogError::UnknownSignature(*signature))?;
+
+    let decoded_data = decoder.decode(log).map_err(LogError::Decode)?;
+    debug!(
+        "Network {} decoded data as hashmap {:?}",
+        network.network_id, &decoded_data
+    );
+
+    let player = decoded_data
+        .get("player")
+        .and_then(|player| player.clone().into_address())
+        .ok_or(LogError::BadField("player"))?;
+    let uint = |name: &'static str| match decoded_data.get(name) {
+        Some(token) => token
+            .clone()
+            .into_uint()
+            .map(|n| BigDecimal::from_str(&n.to_string()).unwrap())
+            .ok_or(LogError::BadField(name)),
+        None => Ok(BigDecimal::default()),
+    };
+
+    let game_result = decode_game_result(&decoded_data);
+
+    let is_end_transaction = decoded_data.contains_key("payout");
+
+    let wager = uint("wager")?;
+    let bets = match decoded_data.get("numGames") {
+        Some(t) => match t.clone().into_uint() {
+            Some(n) => u32::try_from(n).map_err(|_| LogError::BadField("numGames"))? as i64,
+            None => return Err(LogError::BadField("numGames")),
+        },
         None => {
-            warn!("No event signature `{:?}` was found", topics[0]);
-            return;
+            warn!("Could not find token `numGames`");
+            1
+            //return;
         }
     };
-
-    let decoded_data = match ethabi::decode(types, &log.data.0) {
-        Ok(data) => data,
-        Err(e) => {
-            error!(
-                "Network: `{:?}` error on decoding data: {:?}",
-                network.network_id, e
-            );
-            return;
+    let profit = uint("payout")?;
+    let multiplier = match decoded_data
+        .get("multiplier")
+        .and_then(|token| token.clone().into_uint())
+    {
+        Some(multiplier) => {
+            u64::try_from(multiplier).map_err(|_| LogError::BadField("multiplier"))? as f64
+                / game.multiplier_precision as f64
         }
+        None => compute_multiplier(&wager, bets, &profit),
     };
-    debug!("Decoded data {:?}", &decoded_data);
-    let decoded_data: HashMap<String, EthToken> = names
-        .iter()
-        .cloned()
-        .zip(decoded_data.into_iter())
-        .collect();
-    debug!("Decoded data as hashmap {:?}", &decoded_data);
-
-    let player_hand: Option<Vec<u8>> = decoded_data.get("playerHand").cloned().map(|token| {
-        token
-            .into_fixed_array()
-            .unwrap()
-            .iter()
-            .map(|token| token.clone().into_uint().unwrap().as_u32() as u8)
-            .collect()
-    });
-
-    let player_hand: Option<Vec<Card>> = player_hand.map(|player_hand| {
-        player_hand
-            .chunks(2)
-            .map(|chunk| Card {
-                number: chunk[0],
-                suit: chunk[1],
-            })
-            .collect()
-    });
-
-    let is_end_transaction = decoded_data.get("payout").is_some();
 
     let bet = BetInfoResponse {
         id: 0,
-        transaction_hash: format!("0x{}", hex::encode(log.transaction_hash.unwrap().0)),
-        player: format!("0x{}", hex::encode(&topics[1].0[12..])),
-        timestamp: Utc::now(),
+        transaction_hash: format!(
+            "0x{}",
+            hex::encode(
+                log.transaction_hash
+                    .ok_or(LogError::BadField("transactionHash"))?
+                    .0
+            )
+        ),
+        player: format!("0x{}", hex::encode(player.0)),
+        timestamp,
         game_id: game.id,
-        wager: match decoded_data.get("wager") {
-            Some(n) => BigDecimal::from_str(&n.clone().into_uint().unwrap().to_string()).unwrap(),
-            None => BigDecimal::default(),
-        },
+        wager,
         token_address: match decoded_data.get("tokenAddress") {
             Some(token_address) => format!(
                 "0x{}",
-                hex::encode(token_address.clone().into_address().unwrap().0)
+                hex::encode(
+                    token_address
+                        .clone()
+                        .into_address()
+                        .ok_or(LogError::BadField("tokenAddress"))?
+                        .0
+                )
             ),
             None => "".to_string(),
         },
         network_id: game.network_id,
-        bets: match decoded_data.get("numGames") {
-            Some(t) => match t.clone().into_uint() {
-                Some(n) => n.as_u32() as i64,
-                None => {
-                    error!("Could not parse uint for `numGames`");
-                    return;
-                }
-            },
-            None => {
-                warn!("Could not find token `numGames`");
-                1
-                //return;
-            }
-        },
-        multiplier: 1.0,
-        profit: match decoded_data.get("payout") {
-            Some(n) => BigDecimal::from_str(&n.clone().into_uint().unwrap().to_string()).unwrap(),
-            None => BigDecimal::default(),
-        },
+        bets,
+        multiplier,
+        profit,
 
         player_nickname: Default::default(),
         game_name: Default::default(),
         token_name: Default::default(),
         network_name: Default::default(),
-        player_hand,
+        player_hand: game_result
+            .as_ref()
+            .and_then(|result| result.player_hand.clone()),
+        game_result,
+        confirmed: false,
+    };
+
+    Ok((bet, game, is_end_transaction))
+}
+
+/// Keeps the raw log which couldn't be turned into a bet
+fn log_dead_letter(
+    network_id: i64,
+    log: &Log,
+    block_timestamp: Option<DateTime<Utc>>,
+    reason: &LogError,
+) -> LogDeadLetter {
+    let hash = |hash: Option<H256>| {
+        hash.map(|hash| format!("0x{}", hex::encode(hash.0)))
+            .unwrap_or_default()
     };
+    LogDeadLetter {
+        id: 0,
+        network_id,
+        block_number: log.block_number.unwrap_or_default().as_u64() as i64,
+        block_hash: hash(log.block_hash),
+        transaction_hash: hash(log.transaction_hash),
+        log_index: log.log_index.unwrap_or_default().as_u64() as i64,
+        address: format!("0x{}", hex::encode(log.address.0)),
+        topics: log
+            .topics
+            .iter()
+            .map(|topic| format!("0x{}", hex::encode(topic.0)))
+            .collect(),
+        data: format!("0x{}", hex::encode(&log.data.0)),
+        reason: reason.to_string(),
+        block_timestamp,
+        timestamp: Utc::now(),
+    }
+}
+
+/// Restores the log out of its dead letter
+fn dead_letter_log(dead_letter: &LogDeadLetter) -> Option<Log> {
+    Some(Log {
+        address: parse_address(&dead_letter.address).ok()?,
+        topics: dead_letter
+            .topics
+            .iter()
+            .map(|topic| parse_signature(topic).ok())
+            .collect::<Option<Vec<H256>>>()?,
+        data: Bytes(hex::decode(dead_letter.data.get(2..)?).ok()?),
+        block_hash: Some(parse_signature(&dead_letter.block_hash).ok()?),
+        block_number: Some(dead_letter.block_number.into()),
+        transaction_hash: Some(parse_signature(&dead_letter.transaction_hash).ok()?),
+        transaction_index: None,
+        log_index: Some(dead_letter.log_index.into()),
+        transaction_log_index: None,
+        log_type: None,
+        removed: None,
+    })
+}
+
+/// Decodes the dead letters of the network again with its current games, stores
+/// the recovered bets, returns the amounts of the recovered and the still failing logs
+pub async fn reprocess_dead_letters(
+    db: &DB,
+    network: &NetworkInfo,
+) -> Result<(u64, u64), sqlx::Error> {
+    let games = load_games(db, network.network_id).await?;
+    let (mut recovered, mut failed) = (0, 0);
+    for dead_letter in db.query_all_log_dead_letters(network.network_id).await? {
+        let log = match dead_letter_log(&dead_letter) {
+            Some(log) => log,
+            None => {
+                error!("Dead letter {} is malformed", dead_letter.id);
+                failed += 1;
+                continue;
+            }
+        };
+        let decoded = dead_letter
+            .block_timestamp
+            .ok_or(LogError::BadField("blockTimestamp"))
+            .and_then(|timestamp| decode_game_log(&log, network, &games, timestamp));
+        match decoded {
+            Ok((bet, _, is_end_transaction)) => {
+                if is_end_transaction {
+                    db.place_bet(&into_db_bet(
+                        bet,
+                        dead_letter.block_number as u64,
+                        log.block_hash.unwrap_or_default(),
+                        dead_letter.log_index as u64,
+                    ))
+                    .await?;
+                }
+                db.delete_log_dead_letter(dead_letter.id).await?;
+                recovered += 1;
+            }
+            Err(e) => {
+                db.add_log_dead_letter(&LogDeadLetter {
+                    reason: e.to_string(),
+                    ..dead_letter
+                })
+                .await?;
+                failed += 1;
+            }
+        }
+    }
+    info!(
+        "Network {} dead letters reprocessed: {} recovered, {} failed",
+        network.network_id, recovered, failed
+    );
+
+    Ok((recovered, failed))
+}
+
+/// Payout relative to the total wager of all the games of the bet
+fn compute_multiplier(wager: &BigDecimal, bets: i64, payout: &BigDecimal) -> f64 {
+    let total_wager = wager * BigDecimal::from(bets);
+    if total_wager <= BigDecimal::default() {
+        return 0.0;
+    }
+    (payout / total_wager).to_f64().unwrap_or(0.0)
+}
+
+fn uint_list(token: &EthToken) -> Option<Vec<U256>> {
+    match token {
+        EthToken::Array(tokens) | EthToken::FixedArray(tokens) => tokens
+            .iter()
+            .map(|token| token.clone().into_uint())
+            .collect(),
+        _ => None,
+    }
+}
+
+/// Collects the game specific fields of the event, `None` if the game has none of them
+fn decode_game_result(decoded_data: &HashMap<String, EthToken>) -> Option<GameResult> {
+    let small_uints = |name: &str| {
+        decoded_data
+            .get(name)
+            .and_then(uint_list)
+            .map(|values| values.iter().map(|value| value.low_u64()).collect())
+    };
+
+    let result = GameResult {
+        player_hand: decoded_data
+            .get("playerHand")
+            .and_then(uint_list)
+            .map(|player_hand| {
+                player_hand
+                    .chunks(2)
+                    .map(|chunk| Card {
+                        number: chunk[0].low_u32() as u8,
+                        suit: chunk.get(1).map(|suit| suit.low_u32() as u8).unwrap_or(0),
+                    })
+                    .collect()
+            }),
+        coin_outcomes: small_uints("coinOutcomes"),
+        dice_outcomes: small_uints("diceOutcomes"),
+        outcomes: small_uints("outcomes"),
+        random_actions: small_uints("randomActions"),
+        payouts: decoded_data
+            .get("payouts")
+            .and_then(uint_list)
+            .map(|payouts| payouts.iter().map(|payout| payout.to_string()).collect()),
+        outcome: decoded_data
+            .get("outcome")
+            .and_then(|token| token.clone().into_uint())
+            .map(|outcome| outcome.low_u64()),
+    };
+
+    if result.player_hand.is_none()
+        && result.coin_outcomes.is_none()
+        && result.dice_outcomes.is_none()
+        && result.outcomes.is_none()
+        && result.random_actions.is_none()
+        && result.payouts.is_none()
+        && result.outcome.is_none()
+    {
+        return None;
+    }
+    Some(result)
+}
+
+/// Fetches the time of every block the logs are in, once per block
+async fn block_timestamps(
+    rpcs: &SharedRpcPool,
+    logs: &[Log],
+) -> Result<HashMap<u64, DateTime<Utc>>, web3::Error> {
+    let mut timestamps = HashMap::new();
+    for number in logs.iter().filter_map(|log| log.block_number) {
+        let number = number.as_u64();
+        if timestamps.contains_key(&number) {
+            continue;
+        }
+        let block = rpcs
+            .lock()
+            .await
+            .call(|web3| async move {
+                web3.eth()
+                    .block(BlockId::Number(BlockNumber::Number(number.into())))
+                    .await
+            })
+            .await?
+            .ok_or_else(|| web3::Error::InvalidResponse(format!("No block {}", number)))?;
+        let timestamp =
+            DateTime::from_timestamp(block.timestamp.low_u64() as i64, 0).ok_or_else(|| {
+                web3::Error::InvalidResponse(format!("Bad timestamp of the block {}", number))
+            })?;
+        timestamps.insert(number, timestamp);
+    }
+
+    Ok(timestamps)
+}
+
+/// Block number, block hash and index of a mined log, pending logs or bad rpc responses lack them
+fn log_position(log: &Log) -> Result<(u64, H256, u64), LogError> {
+    let block_id = log.block_number.ok_or(LogError::BadField("blockNumber"))?;
+    let block_hash = log.block_hash.ok_or(LogError::BadField("blockHash"))?;
+    let log_index = log.log_index.ok_or(LogError::BadField("logIndex"))?;
+    Ok((block_id.as_u64(), block_hash, log_index.as_u64()))
+}
+
+fn into_db_bet(bet: BetInfoResponse, block_id: u64, block_hash: H256, log_index: u64) -> Bet {
+    let mut db_bet: Bet = bet.into();
+    db_bet.block_number = block_id as i64;
+    db_bet.block_hash = format!("0x{}", hex::encode(block_hash.0));
+    db_bet.log_index = log_index as i64;
+    db_bet
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn handle_game_log(
+    log: Log,
+    network: &NetworkInfo,
+    games: &GameInnerInfo,
+    bets: &mut Vec<Bet>,
+    dead_letters: &mut Vec<LogDeadLetter>,
+    bet_sender: &BetSender,
+    pending: &mut VecDeque<PendingBet>,
+    block_id: u64,
+    block_hash: H256,
+    log_index: u64,
+    timestamp: DateTime<Utc>,
+) {
+    let (mut bet, game, is_end_transaction) = match decode_game_log(&log, network, games, timestamp)
+    {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            error!(
+                "Network {}: dropping log of the transaction {:?}: {}",
+                network.network_id, log.transaction_hash, e
+            );
+            dead_letters.push(log_dead_letter(
+                network.network_id,
+                &log,
+                Some(timestamp),
+                &e,
+            ));
+            return;
+        }
+    };
+
+    let confirmed = is_end_transaction && network.confirmations <= 0;
+    bet.confirmed = confirmed;
+
+    let propagated = PropagatedBet {
+        bet: bet.clone(),
+        game_name: game.name.clone(),
+        network_name: network.network_name.clone(),
+        reverted: false,
+    };
+
+    if is_end_transaction {
+        let db_bet = DbPropagatedBet {
+            bet: into_db_bet(bet, block_id, block_hash, log_index),
+            block_id,
+        };
+        if confirmed {
+            bets.push(db_bet.bet);
+        } else {
+            pending.push_back(PendingBet {
+                bet: db_bet,
+                propagated: propagated.clone(),
+            });
+        }
+    }
+
+    if let Err(e) = bet_sender.send(propagated) {
+        error!("Error propagating bet {:?}", e);
+    }
+}
+
+/// Moves the pending bets that are at least `confirmations` blocks deep
+/// to the bets to persist and streams them once more as confirmed
+fn release_confirmed_bets(
+    network: &NetworkInfo,
+    pending: &mut VecDeque<PendingBet>,
+    head: u64,
+    bets: &mut Vec<Bet>,
+    bet_sender: &BetSender,
+) {
+    while let Some(pending_bet) = pending.front() {
+        if pending_bet.bet.block_id + network.confirmations as u64 > head {
+            break;
+        }
+        let PendingBet {
+            bet,
+            mut propagated,
+        } = pending.pop_front().unwrap();
+        bets.push(bet.bet);
 
-    if is_end_transaction {
-        if let Err(e) = db_sender.send(DbMessage::PlaceBet(DbPropagatedBet {
-            bet: bet.clone().into(),
-            block_id,
-        })) {
-            error!("Error sending bet to db {:?}", e);
-            return;
+        propagated.bet.confirmed = true;
+        if let Err(e) = bet_sender.send(propagated) {
+            error!("Error propagating bet {:?}", e);
         }
     }
+}
 
-    if let Err(e) = bet_sender.send(PropagatedBet {
-        bet,
-        game_name: game.name.clone(),
-        network_name: network.network_name.clone(),
-    }) {
-        error!("Error propagating bet {:?}", e);
+/// Drops the pending bets placed after the `ancestor` block
+fn revert_pending_bets(pending: &mut VecDeque<PendingBet>, ancestor: u64, bet_sender: &BetSender) {
+    while let Some(pending_bet) = pending.back() {
+        if pending_bet.bet.block_id <= ancestor {
+            break;
+        }
+        let mut propagated = pending.pop_back().unwrap().propagated;
+        propagated.reverted = true;
+        if let Err(e) = bet_sender.send(propagated) {
+            error!("Error propagating reverted bet {:?}", e);
+        }
+    }
+}
+
+/// Persists the bets of the poll along with the block the network is resumed from,
+/// the unconfirmed bets aren't persisted yet so their blocks are scanned again after a restart
+async fn send_poll(
+    network: &NetworkInfo,
+    pending: &VecDeque<PendingBet>,
+    last_block: Option<u64>,
+    bets: Vec<Bet>,
+    dead_letters: Vec<LogDeadLetter>,
+    db_sender: &DbSender,
+) -> Option<u64> {
+    let cursor = pending
+        .iter()
+        .map(|pending_bet| pending_bet.bet.block_id)
+        .chain(last_block)
+        .min()?;
+    if let Err(e) = db_sender
+        .send(DbMessage::Poll(DbPoll {
+            network_id: network.network_id,
+            bets,
+            dead_letters,
+            last_block: cursor,
+        }))
+        .await
+    {
+        error!("Error sending bets to db {:?}", e);
     }
+    Some(cursor)
 }
 
-#[allow(unused_assignments)]
+/// Refreshes the USD prices of the network's tokens from their price sources
 pub async fn token_price_handler(
-    _network: NetworkInfo,
-    rpc_urls: Vec<String>,
-    tokens: Vec<Token>,
+    network: NetworkInfo,
+    tokens: Vec<(Token, Box<dyn PriceSource>)>,
     db_sender: DbSender,
-    usdt_token_address: String,
-    router_address: String,
 ) {
-    let abi_file = File::open("./abis/pancake.json").unwrap();
-    let mut router_address_wrapped: [u8; 20] = [0; 20];
-    hex::decode_to_slice(router_address[2..].as_bytes(), &mut router_address_wrapped).unwrap();
-
-    let transport = rpc_urls
-        .iter()
-        .find_map(|url| web3::transports::Http::new(url).ok())
-        .unwrap();
-
-    debug!("Starting listening to rpc: {:?}", transport);
-
-    let web3 = web3::Web3::new(transport);
-
-    let contract = Contract::new(
-        web3.eth(),
-        H160::from_slice(&router_address_wrapped),
-        ethabi::Contract::load(abi_file).unwrap(),
-    );
-
-    let bnb_text_token = "0xbb4CdB9CBd36B01bD1cBaEBF2De08d9173bc095c";
-    let mut bnb_address: [u8; 20] = [0; 20];
-    hex::decode_to_slice(bnb_text_token[2..].as_bytes(), &mut bnb_address).unwrap();
-    let bnb_address = H160::from(bnb_address);
-
-    let mut usdt_address: [u8; 20] = [0; 20];
-    hex::decode_to_slice(usdt_token_address[2..].as_bytes(), &mut usdt_address).unwrap();
-    let usdt_address = H160::from(usdt_address);
-
     loop {
-        let amount: Vec<U256> = match contract
-            .query(
-                "getAmountsOut",
-                (
-                    U256::from(1000000000000000000u64),
-                    vec![bnb_address, usdt_address],
-                ),
-                None,
-                Default::default(),
-                None,
-            )
-            .await
-        {
-            Ok(amount) => amount,
-            Err(e) => {
-                error!("Error on getting ammounts {:?}", e);
-                sleep(Duration::from_secs(180)).await;
-                continue;
-            }
-        };
-
-        let bnb_price = Decimal::from(amount[1].as_u128()) / Decimal::from(1000000000000000000u64);
-        for token in tokens.iter() {
-            let mut token_address: [u8; 20] = [0; 20];
-            hex::decode_to_slice(token.contract_address[2..].as_bytes(), &mut token_address)
-                .unwrap();
-            let token_address = H160::from(token_address);
-
-            let amount: Vec<U256> = match contract
-                .query(
-                    "getAmountsOut",
-                    (
-                        U256::from(1000000000000000000u64),
-                        vec![token_address, bnb_address],
-                    ),
-                    None,
-                    Default::default(),
-                    None,
-                )
-                .await
-            {
-                Ok(amount) => amount,
+        for (token, source) in tokens.iter() {
+            let token_price = match source.price(token).await {
+                Ok(price) => price.to_f64().unwrap_or_default(),
                 Err(e) => {
-                    error!("Error on getting ammounts {:?}", e);
-                    sleep(Duration::from_secs(180)).await;
+                    error!(
+                        "Network {}: error getting price for {:?}: {}",
+                        network.network_id, token.contract_address, e
+                    );
                     continue;
                 }
             };
 
-            let token_price =
-                Decimal::from(amount[1].as_u128()) / Decimal::from(1000000000000000000u64);
-
-            let token_price = (token_price * bnb_price).to_f64().unwrap();
-
-            if let Err(e) = db_sender.send(DbMessage::NewPrice(TokenPrice {
-                id: 0,
-                token_name: token.name.clone(),
-                price: token_price,
-            })) {
+            if let Err(e) = db_sender
+                .send(DbMessage::NewPrice(TokenPrice {
+                    id: 0,
+                    token_name: token.name.clone(),
+                    price: token_price,
+                }))
+                .await
+            {
                 error!(
                     "Error getting price for {:?}: {:?}",
                     token.contract_address, e
@@ -336,100 +977,400 @@ pub async fn token_price_handler(
 
             debug!("{:?} price: {:?}", token.name, token_price);
         }
-        sleep(Duration::from_secs(180)).await;
+        sleep(PRICE_UPDATE_INTERVAL).await;
     }
 }
 
-#[allow(unused_assignments)]
+#[allow(clippy::too_many_arguments)]
 pub async fn network_handler(
     network: NetworkInfo,
-    rpc_urls: Vec<String>,
-    games: GameInnerInfo,
+    rpcs: SharedRpcPool,
+    mut games: GameRegistry,
     db_sender: DbSender,
     bet_sender: BetSender,
     mut last_block: Option<u64>,
+    mut blocks: BlockTracker,
+    mut shutdown: ShutdownReceiver,
 ) {
-    //let mut restart = true;
+    let mut pending: VecDeque<PendingBet> = VecDeque::new();
 
-    loop {
-        //restart = false;
-        let transport = rpc_urls
-            .iter()
-            .find_map(|url| web3::transports::Http::new(url).ok())
-            .unwrap();
+    while !*shutdown.borrow() {
+        let head = rpcs.lock().await.update_heights().await;
+        let head = match head {
+            Some(head) => head,
+            None => {
+                error!(
+                    "network id `{:?}`: Error getting the latest block",
+                    network.network_id
+                );
+                sleep_or_shutdown(&mut shutdown, Duration::from_millis(5000)).await;
+                continue;
+            }
+        };
+
+        if last_block.is_none() {
+            last_block.replace(head);
+            debug!(
+                "Network {} Latest block id {:?}",
+                network.network_id, last_block
+            );
+        }
 
-        debug!("Starting listening to rpc: {:?}", transport);
+        let tracked = &blocks;
+        let reorg = rpcs
+            .lock()
+            .await
+            .call(|web3| async move { tracked.find_reorg(&web3).await })
+            .await;
+        match reorg {
+            Ok(Some(ancestor)) => {
+                warn!(
+                    "Network {} reorg detected, rolling back to the block {}",
+                    network.network_id, ancestor
+                );
+                blocks.rewind(ancestor);
+                revert_pending_bets(&mut pending, ancestor, &bet_sender);
+                last_block.replace(ancestor + 1);
+                if let Err(e) = db_sender
+                    .send(DbMessage::Reorg(DbReorg {
+                        network_id: network.network_id,
+                        from_block: ancestor + 1,
+                    }))
+                    .await
+                {
+                    error!("Error sending reorg to db {:?}", e);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => {
+                error!(
+                    "network id `{:?}`: Error checking for reorgs `{:?}`",
+                    network.network_id, e
+                );
+            }
+        }
+
+        let from_block = last_block.unwrap();
+        if from_block > head {
+            sleep_or_shutdown(&mut shutdown, Duration::from_millis(5000)).await;
+            continue;
+        }
+        // the cursor is kept, new games are only tracked from it on
+        games.refresh().await;
+        let games = games.games();
+        // the range is bounded, public nodes reject huge ones after a downtime
+        let to_block = head.min(from_block + *BACKFILL_WINDOW - 1);
+        let catching_up = to_block < head;
 
-        let web3 = web3::Web3::new(transport);
+        let filter = FilterBuilder::default()
+            .address(games.values().map(|(address, _, _)| *address).collect())
+            .topics(Some(games.keys().copied().collect()), None, None, None)
+            .from_block(from_block.into())
+            .to_block(to_block.into())
+            .build();
 
-        if last_block.is_none() {
-            last_block.replace(match web3.eth().block_number().await {
-                Ok(block_number) => block_number.as_u64(),
+        let logs = if games.is_empty() {
+            // an empty filter would match the logs of every contract
+            Ok(Vec::new())
+        } else {
+            rpcs.lock()
+                .await
+                .call(|web3| async move { web3.eth().logs(filter).await })
+                .await
+        };
+        let logs = match logs {
+            Ok(logs) => logs,
+            Err(e) => {
+                error!(
+                    "network id `{:?}`: Error creating filter `{:?}`",
+                    network.network_id, e
+                );
+                continue;
+            }
+        };
+
+        debug!(
+            "Network `{}` got {} logs in blocks {}..={}",
+            network.network_id,
+            logs.len(),
+            from_block,
+            to_block
+        );
+
+        let timestamps = match block_timestamps(&rpcs, &logs).await {
+            Ok(timestamps) => timestamps,
+            Err(e) => {
+                error!(
+                    "network id `{:?}`: Error getting block timestamps `{:?}`",
+                    network.network_id, e
+                );
+                continue;
+            }
+        };
+
+        if logs.is_empty() && !catching_up {
+            let block = rpcs
+                .lock()
+                .await
+                .call(|web3| async move {
+                    web3.eth()
+                        .block(BlockId::Number(BlockNumber::Number(to_block.into())))
+                        .await
+                })
+                .await;
+            match block {
+                Ok(Some(Block {
+                    number: Some(number),
+                    hash: Some(hash),
+                    ..
+                })) => {
+                    blocks.track(number.as_u64(), hash);
+                }
+                Ok(_) => {
+                    error!(
+                        "network id `{:?}`: Block {} is not available",
+                        network.network_id, to_block
+                    );
+                    continue;
+                }
                 Err(e) => {
                     error!(
-                        "network id `{:?}`: Error creating filter `{:?}`",
+                        "network id `{:?}`: Error getting block `{:?}`",
                         network.network_id, e
                     );
                     continue;
                 }
-            });
+            }
+        }
+
+        let mut bets = Vec::new();
+        let mut dead_letters = Vec::new();
+        for log in logs {
+            let (block_id, block_hash, log_index) = match log_position(&log) {
+                Ok(position) => position,
+                Err(e) => {
+                    warn!(
+                        "Network {}: skipping log of the transaction {:?}: {}",
+                        network.network_id, log.transaction_hash, e
+                    );
+                    let timestamp = log
+                        .block_number
+                        .and_then(|number| timestamps.get(&number.as_u64()))
+                        .copied();
+                    dead_letters.push(log_dead_letter(network.network_id, &log, timestamp, &e));
+                    continue;
+                }
+            };
+            blocks.track(block_id, block_hash);
+            handle_game_log(
+                log,
+                &network,
+                games,
+                &mut bets,
+                &mut dead_letters,
+                &bet_sender,
+                &mut pending,
+                block_id,
+                block_hash,
+                log_index,
+                timestamps[&block_id],
+            )
+            .await;
         }
+        last_block.replace(to_block + 1);
+
+        release_confirmed_bets(&network, &mut pending, head, &mut bets, &bet_sender);
+        // the cursor moves even if no bets were placed
+        send_poll(
+            &network,
+            &pending,
+            last_block,
+            bets,
+            dead_letters,
+            &db_sender,
+        )
+        .await;
 
         debug!(
             "Network {} Latest block id {:?}",
             network.network_id, last_block
         );
 
-        loop {
-            let filter = FilterBuilder::default()
-                .address(games.iter().map(|item| item.1 .0).collect())
-                .limit(40)
-                .from_block(last_block.unwrap().into())
-                .build();
+        if catching_up {
+            sleep_or_shutdown(&mut shutdown, *BACKFILL_DELAY).await;
+        } else {
+            sleep_or_shutdown(&mut shutdown, Duration::from_millis(5000)).await;
+        }
+    }
+
+    let cursor = send_poll(
+        &network,
+        &pending,
+        last_block,
+        Vec::new(),
+        Vec::new(),
+        &db_sender,
+    )
+    .await;
+    info!(
+        "Network {} handler stopped at block {:?}",
+        network.network_id, cursor
+    );
+}
+
+/// Processes the requested historical block ranges of the network
+/// independently of the live tail, the bets aren't streamed to the feed
+pub async fn backfill_handler(
+    network: NetworkInfo,
+    rpcs: SharedRpcPool,
+    db: DB,
+    mut shutdown: ShutdownReceiver,
+) {
+    while !*shutdown.borrow() {
+        match db.query_pending_backfill_jobs(network.network_id).await {
+            Ok(jobs) if jobs.is_empty() => {}
+            Ok(jobs) => match load_games(&db, network.network_id).await {
+                Ok(games) => {
+                    for job in jobs {
+                        run_backfill_job(&network, &rpcs, &games, &db, job, &mut shutdown).await;
+                    }
+                }
+                Err(e) => {
+                    error!("Error getting games to backfill {:?}", e);
+                }
+            },
+            Err(e) => {
+                error!("Error getting backfill jobs {:?}", e);
+            }
+        }
+        sleep_or_shutdown(&mut shutdown, BACKFILL_POLL).await;
+    }
+}
+
+/// Processes the job window by window, its progress is saved after every window
+/// so a job interrupted by the shutdown is resumed on the next start
+async fn run_backfill_job(
+    network: &NetworkInfo,
+    rpcs: &SharedRpcPool,
+    games: &GameInnerInfo,
+    db: &DB,
+    job: BackfillJob,
+    shutdown: &mut ShutdownReceiver,
+) {
+    let (addresses, signatures): (Vec<H160>, Vec<H256>) = games
+        .iter()
+        .filter(|(_, (_, _, game))| !matches!(job.game_id, Some(game_id) if game_id != game.id))
+        .map(|(signature, (address, _, _))| (*address, *signature))
+        .unzip();
+    if addresses.is_empty() {
+        warn!("Backfill job {}: no games to backfill", job.id);
+        if let Err(e) = db.set_backfill_progress(job.id, job.to_block, true).await {
+            error!("Error saving backfill progress {:?}", e);
+        }
+        return;
+    }
+
+    let mut from_block = job
+        .processed_block
+        .map(|block| block + 1)
+        .unwrap_or(job.from_block) as u64;
+    let last_block = job.to_block as u64;
+    debug!(
+        "Backfill job {}: network {} blocks {}..={}",
+        job.id, network.network_id, from_block, last_block
+    );
+
+    while from_block <= last_block {
+        if *shutdown.borrow() {
+            debug!("Backfill job {} stopped at block {}", job.id, from_block);
+            return;
+        }
+        let to_block = last_block.min(from_block + *BACKFILL_WINDOW - 1);
+        let filter = FilterBuilder::default()
+            .address(addresses.clone())
+            .topics(Some(signatures.clone()), None, None, None)
+            .from_block(from_block.into())
+            .to_block(to_block.into())
+            .build();
+
+        let logs = rpcs
+            .lock()
+            .await
+            .call(|web3| async move { web3.eth().logs(filter).await })
+            .await;
+        let logs = match logs {
+            Ok(logs) => logs,
+            Err(e) => {
+                error!("Backfill job {}: error getting logs `{:?}`", job.id, e);
+                sleep_or_shutdown(shutdown, *BACKFILL_DELAY).await;
+                continue;
+            }
+        };
+
+        let timestamps = match block_timestamps(rpcs, &logs).await {
+            Ok(timestamps) => timestamps,
+            Err(e) => {
+                error!(
+                    "Backfill job {}: error getting block timestamps `{:?}`",
+                    job.id, e
+                );
+                sleep_or_shutdown(shutdown, *BACKFILL_DELAY).await;
+                continue;
+            }
+        };
 
-            let logs = match web3.eth().logs(filter).await {
-                Ok(logs) => logs,
+        for log in logs {
+            let (block_id, block_hash, log_index) = match log_position(&log) {
+                Ok(position) => position,
                 Err(e) => {
-                    error!(
-                        "network id `{:?}`: Error creating filter `{:?}`",
-                        network.network_id, e
-                    );
+                    warn!("Backfill job {}: skipping log: {}", job.id, e);
+                    let timestamp = log
+                        .block_number
+                        .and_then(|number| timestamps.get(&number.as_u64()))
+                        .copied();
+                    let dead_letter = log_dead_letter(network.network_id, &log, timestamp, &e);
+                    if let Err(e) = db.add_log_dead_letter(&dead_letter).await {
+                        error!("Error saving dead letter {:?}", e);
+                    }
                     continue;
                 }
             };
-
-            debug!("Network `{}` got {} logs", network.network_id, logs.len());
-
-            if logs.is_empty() {
-                last_block.replace(match web3.eth().block_number().await {
-                    Ok(block_number) => block_number.as_u64(),
+            let timestamp = timestamps[&block_id];
+            let (bet, game, is_end_transaction) =
+                match decode_game_log(&log, network, games, timestamp) {
+                    Ok(decoded) => decoded,
                     Err(e) => {
-                        error!(
-                            "network id `{:?}`: Error creating filter `{:?}`",
-                            network.network_id, e
-                        );
+                        error!("Backfill job {}: dropping log: {}", job.id, e);
+                        let dead_letter =
+                            log_dead_letter(network.network_id, &log, Some(timestamp), &e);
+                        if let Err(e) = db.add_log_dead_letter(&dead_letter).await {
+                            error!("Error saving dead letter {:?}", e);
+                        }
                         continue;
                     }
-                });
+                };
+            if !is_end_transaction || job.game_id.is_some_and(|game_id| game_id != game.id) {
+                continue;
             }
-
-            for log in logs {
-                let block_id = log
-                    .block_number
-                    .map(|id| id.as_u64())
-                    .expect("No block id found");
-                handle_game_log(log, &network, &games, &db_sender, &bet_sender, block_id).await;
-                last_block.replace(block_id + 1);
+            if let Err(e) = db
+                .place_bet(&into_db_bet(bet, block_id, block_hash, log_index))
+                .await
+            {
+                error!("Error placing bet {:?}", e);
             }
+        }
 
-            debug!(
-                "Network {} Latest block id {:?}",
-                network.network_id, last_block
-            );
-
-            sleep(Duration::from_millis(5000)).await;
+        if let Err(e) = db
+            .set_backfill_progress(job.id, to_block as i64, to_block == last_block)
+            .await
+        {
+            error!("Error saving backfill progress {:?}", e);
         }
+        from_block = to_block + 1;
+
+        sleep_or_shutdown(shutdown, *BACKFILL_DELAY).await;
     }
+    debug!("Backfill job {} is done", job.id);
 }
 
 pub async fn bet_listener(db: DB, mut bet_receiver: BetReceiver, ws_data_feed: WsDataFeedSender) {
@@ -456,8 +1397,15 @@ pub async fn bet_listener(db: DB, mut bet_receiver: BetReceiver, ws_data_feed: W
                 multiplier: bet.bet.multiplier,
                 profit: bet.bet.profit.clone(),
                 player_hand: bet.bet.player_hand,
+                game_result: bet.bet.game_result,
+                confirmed: bet.bet.confirmed,
+            };
+            let message = if bet.reverted {
+                WsDataFeedMessage::BetReverted(bet_info)
+            } else {
+                WsDataFeedMessage::Bet(bet_info)
             };
-            if let Err(e) = ws_data_feed.send(bet_info) {
+            if let Err(e) = ws_data_feed.send(message) {
                 error!("Error sending bet to the ws feed {:?}", e);
             }
         } else {
@@ -483,33 +1431,319 @@ pub async fn bet_listener(db: DB, mut bet_receiver: BetReceiver, ws_data_feed: W
                 multiplier: bet.bet.multiplier,
                 profit: bet.bet.profit.clone(),
                 player_hand: bet.bet.player_hand,
+                game_result: bet.bet.game_result,
+                confirmed: bet.bet.confirmed,
             };
-            if let Err(e) = ws_data_feed.send(bet_info) {
+            let message = if bet.reverted {
+                WsDataFeedMessage::BetReverted(bet_info)
+            } else {
+                WsDataFeedMessage::Bet(bet_info)
+            };
+            if let Err(e) = ws_data_feed.send(message) {
                 error!("Error sending bet to the ws feed {:?}", e);
             }
         }
     }
 }
 
-pub async fn db_listener(mut receiver: DbReceiver, db: DB) {
+/// Stores the bets of the poll retrying the failed transaction, after the last retry
+/// the bets are stored one by one moving the failing ones to the dead letters
+async fn store_poll(db: &DB, poll: DbPoll, metrics: &DbQueueMetrics) {
+    let last_block = poll.last_block as i64;
+    let mut attempt = 0;
+    loop {
+        match db
+            .place_bets(poll.network_id, &poll.bets, &poll.dead_letters, last_block)
+            .await
+        {
+            Ok(()) => return,
+            Err(e) if attempt < *DB_RETRIES => {
+                attempt += 1;
+                metrics.retries.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "Network {}: error placing {} bets, retry {} of {}: {:?}",
+                    poll.network_id,
+                    poll.bets.len(),
+                    attempt,
+                    *DB_RETRIES,
+                    e
+                );
+                sleep(*DB_RETRY_DELAY).await;
+            }
+            Err(e) => {
+                error!(
+                    "Network {}: error placing {} bets, storing them one by one: {:?}",
+                    poll.network_id,
+                    poll.bets.len(),
+                    e
+                );
+                break;
+            }
+        }
+    }
+
+    for bet in poll.bets.iter() {
+        let e = match db.place_bet(bet).await {
+            Ok(()) => continue,
+            Err(e) => e,
+        };
+        error!(
+            "Bet {} {} moved to the dead letters: {:?}",
+            bet.transaction_hash, bet.log_index, e
+        );
+        metrics.dead_letters.fetch_add(1, Ordering::Relaxed);
+        if let Err(e) = db.add_bet_dead_letter(bet, &e.to_string()).await {
+            error!("Error saving dead letter {:?}", e);
+        }
+    }
+    if let Err(e) = db
+        .place_bets(poll.network_id, &[], &poll.dead_letters, last_block)
+        .await
+    {
+        error!(
+            "Network {}: error saving block cursor {:?}",
+            poll.network_id, e
+        );
+    }
+}
+
+pub async fn db_listener(
+    mut receiver: DbReceiver,
+    db: DB,
+    ws_data_feed: WsDataFeedSender,
+    metrics: SharedDbQueueMetrics,
+) {
     while let Some(msg) = receiver.recv().await {
+        metrics.depth.store(receiver.len(), Ordering::Relaxed);
         match msg {
-            DbMessage::PlaceBet(bet) => {
-                if let Err(e) = db.place_bet(&bet.bet).await {
-                    error!("Error placing bet {:?}", e);
-                }
-                if let Err(e) = db
-                    .set_last_block(bet.bet.network_id, bet.block_id as i64)
+            DbMessage::Poll(poll) => store_poll(&db, poll, &metrics).await,
+            DbMessage::NewPrice(price) => {
+                match db
+                    .change_token_price(&price.token_name, price.price, *MAX_PRICE_DEVIATION)
                     .await
                 {
-                    error!("Error changing block id {:?}", e);
+                    Ok(true) => {}
+                    Ok(false) => warn!("Anomalous price discarded {:?}", price),
+                    Err(e) => error!("Error changing price {:?}: {:?}", price, e),
                 }
             }
-            DbMessage::NewPrice(price) => {
-                if let Err(e) = db.change_token_price(&price.token_name, price.price).await {
-                    error!("Error changing price {:?}: {:?}", price, e);
+            DbMessage::Reorg(reorg) => {
+                let bets = match db
+                    .revert_bets(reorg.network_id, reorg.from_block as i64)
+                    .await
+                {
+                    Ok(bets) => bets,
+                    Err(e) => {
+                        error!("Error reverting bets {:?}", e);
+                        continue;
+                    }
+                };
+                warn!(
+                    "Network {} reverted {} bets from the block {}",
+                    reorg.network_id,
+                    bets.len(),
+                    reorg.from_block
+                );
+                for bet in bets {
+                    if let Err(e) = ws_data_feed.send(WsDataFeedMessage::BetReverted(bet.into())) {
+                        error!("Error sending reverted bet to the ws feed {:?}", e);
+                    }
                 }
             }
         }
     }
+    info!("Db listener stopped, pending messages are written");
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn compute_multiplier_test() {
+        let wager = BigDecimal::from(1000);
+        assert_eq!(compute_multiplier(&wager, 2, &BigDecimal::from(3960)), 1.98);
+        assert_eq!(compute_multiplier(&wager, 1, &BigDecimal::default()), 0.0);
+        assert_eq!(compute_multiplier(&BigDecimal::default(), 1, &wager), 0.0);
+    }
+
+    #[test]
+    fn block_tracker_rewind_test() {
+        let mut tracker = BlockTracker::default();
+        for number in 1..=TRACKED_BLOCKS as u64 + 10 {
+            tracker.track(number, H256::from_low_u64_be(number));
+        }
+        tracker.track(5, H256::zero());
+        assert_eq!(tracker.blocks.len(), TRACKED_BLOCKS);
+        assert_eq!(tracker.blocks.front().unwrap().0, 11);
+
+        tracker.rewind(100);
+        assert_eq!(
+            tracker.blocks.back(),
+            Some(&(100, H256::from_low_u64_be(100)))
+        );
+    }
+
+    #[test]
+    fn dead_letter_log_test() {
+        let log = Log {
+            address: H160::from_low_u64_be(7),
+            topics: vec![H256::from_low_u64_be(1), H256::from_low_u64_be(2)],
+            data: Bytes(vec![0xab, 0xcd]),
+            block_hash: Some(H256::from_low_u64_be(3)),
+            block_number: Some(100.into()),
+            transaction_hash: Some(H256::from_low_u64_be(4)),
+            transaction_index: None,
+            log_index: Some(5.into()),
+            transaction_log_index: None,
+            log_type: None,
+            removed: None,
+        };
+        let dead_letter = log_dead_letter(56, &log, None, &LogError::BadField("player"));
+        assert_eq!(dead_letter.data, "0xabcd");
+        assert_eq!(dead_letter.reason, "Field `player` is missing or malformed");
+        assert_eq!(dead_letter_log(&dead_letter), Some(log.clone()));
+
+        assert_eq!(
+            log_position(&log).unwrap(),
+            (100, H256::from_low_u64_be(3), 5)
+        );
+        let pending = Log {
+            log_index: None,
+            ..log
+        };
+        assert!(matches!(
+            log_position(&pending),
+            Err(LogError::BadField("logIndex"))
+        ));
+    }
+
+    /// Needs the database with the scheme: `DATABASE_URL=... cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn backfilled_bet_price_test() {
+        let signature = H256::from_low_u64_be(1);
+        let games: GameInnerInfo = [(
+            signature,
+            (
+                H160::zero(),
+                EventDecoder::Layout {
+                    types: vec![ethabi::ParamType::Uint(256), ethabi::ParamType::Uint(256)],
+                    names: vec!["wager".to_string(), "payout".to_string()],
+                },
+                GameInfo {
+                    id: 1,
+                    network_id: 56,
+                    name: "game1".to_string(),
+                    address: String::new(),
+                    event_signature: String::new(),
+                    event_types: String::new(),
+                    event_names: String::new(),
+                    event: None,
+                    event_abi: None,
+                    multiplier_precision: 10000,
+                },
+            ),
+        )]
+        .into_iter()
+        .collect();
+        let network = NetworkInfo {
+            network_id: 56,
+            network_name: "BSC".to_string(),
+            short_name: "bsc".to_string(),
+            currency_name: "BNB".to_string(),
+            currency_symbol: "BNB".to_string(),
+            decimals: 18,
+            confirmations: 0,
+        };
+        let log = Log {
+            address: H160::zero(),
+            topics: vec![signature, H256::from(H160::from_low_u64_be(9))],
+            data: Bytes(ethabi::encode(&[
+                EthToken::Uint(1000.into()),
+                EthToken::Uint(2000.into()),
+            ])),
+            block_hash: Some(H256::from_low_u64_be(3)),
+            block_number: Some(100.into()),
+            transaction_hash: Some(H256::from_low_u64_be(4)),
+            transaction_index: None,
+            log_index: Some(5.into()),
+            transaction_log_index: None,
+            log_type: None,
+            removed: None,
+        };
+
+        let placed_at = DateTime::from_timestamp(1_672_531_200, 0).unwrap();
+        let (bet, _, is_end_transaction) =
+            decode_game_log(&log, &network, &games, placed_at).unwrap();
+        assert!(is_end_transaction);
+        assert_eq!(bet.timestamp, placed_at);
+
+        let pool = sqlx::PgPool::connect(&std::env::var("DATABASE_URL").unwrap())
+            .await
+            .unwrap();
+        let mut tx = pool.begin().await.unwrap();
+        sqlx::query(
+            "
+            INSERT INTO TokenPriceHistory(token_name, price, timestamp)
+            VALUES ('price_test', 250, $1), ('price_test', 600, now())
+            ",
+        )
+        .bind((placed_at - chrono::Duration::minutes(3)).naive_utc())
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        let price: f64 = sqlx::query_scalar("SELECT token_price_at('price_test', $1)")
+            .bind(bet.timestamp.naive_utc())
+            .fetch_one(&mut *tx)
+            .await
+            .unwrap();
+        assert_eq!(price, 250.0);
+    }
+
+    #[test]
+    fn games_difference_test() {
+        let game = |id: i64, address: u64, signature: u64| {
+            (
+                H256::from_low_u64_be(signature),
+                (
+                    H160::from_low_u64_be(address),
+                    EventDecoder::Layout {
+                        types: Vec::new(),
+                        names: Vec::new(),
+                    },
+                    GameInfo {
+                        id,
+                        network_id: 56,
+                        name: format!("game{}", id),
+                        address: String::new(),
+                        event_signature: String::new(),
+                        event_types: String::new(),
+                        event_names: String::new(),
+                        event: None,
+                        event_abi: None,
+                        multiplier_precision: 10000,
+                    },
+                ),
+            )
+        };
+        let old: GameInnerInfo = [game(1, 1, 1), game(2, 2, 2)].into_iter().collect();
+        let new: GameInnerInfo = [game(1, 1, 1), game(2, 3, 2), game(3, 4, 4)]
+            .into_iter()
+            .collect();
+
+        let mut added = games_difference(&new, &old);
+        added.sort();
+        assert_eq!(
+            added,
+            vec![
+                (H160::from_low_u64_be(3), H256::from_low_u64_be(2)),
+                (H160::from_low_u64_be(4), H256::from_low_u64_be(4)),
+            ]
+        );
+        assert_eq!(
+            games_difference(&old, &new),
+            vec![(H160::from_low_u64_be(2), H256::from_low_u64_be(2))]
+        );
+    }
 }
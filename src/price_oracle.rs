@@ -0,0 +1,292 @@
+use crate::models::db_models::{PriceSourceConfig, Token};
+use crate::rpc_pool::SharedRpcPool;
+use ethabi::ethereum_types::{H160, U256};
+use futures::future::{join_all, BoxFuture};
+use lazy_static::lazy_static;
+use rust_decimal::Decimal;
+use std::str::FromStr;
+use thiserror::Error;
+use tracing::{debug, warn};
+use web3::contract::Contract;
+
+#[derive(Error, Debug)]
+pub enum PriceError {
+    #[error("Rpc error: {0}")]
+    Rpc(web3::Error),
+
+    #[error("Bad amount `{0}` returned by the router")]
+    BadAmount(String),
+
+    #[error("No source returned a price")]
+    NoPrice,
+
+    #[error("Unsupported decimals `{0}`")]
+    BadDecimals(i64),
+}
+
+lazy_static! {
+    /// Abi of the Uniswap V2 router, `getAmountsOut` is the same on all of its forks
+    static ref UNIV2_ROUTER_ABI: ethabi::Contract =
+        ethabi::Contract::load(include_bytes!("../abis/univ2_router.json").as_slice())
+            .expect("Malformed Uniswap V2 router abi");
+}
+
+/// Most decimals of a token, one whole token in its smallest units has to fit into `Decimal`
+pub const MAX_DECIMALS: i64 = 28;
+
+/// Provider of the USD price of a token
+pub trait PriceSource: Send + Sync {
+    /// Price of one whole token
+    fn price<'a>(&'a self, token: &'a Token) -> BoxFuture<'a, Result<Decimal, PriceError>>;
+}
+
+fn parse_address(address: &str) -> Option<H160> {
+    let mut parsed: [u8; 20] = [0; 20];
+    hex::decode_to_slice(address.get(2..)?, &mut parsed).ok()?;
+    Some(H160::from(parsed))
+}
+
+/// One whole token in its smallest units, `None` for the decimals above `MAX_DECIMALS`
+pub fn decimals_unit(decimals: i64) -> Option<Decimal> {
+    if !(0..=MAX_DECIMALS).contains(&decimals) {
+        return None;
+    }
+    Decimal::try_from_i128_with_scale(10i128.pow(decimals as u32), 0).ok()
+}
+
+/// Quotes the token through `getAmountsOut` of a Uniswap V2 style router,
+/// the path has to end with a USD stablecoin
+pub struct UniV2Router {
+    rpcs: SharedRpcPool,
+    router: H160,
+    path: Vec<H160>,
+    quote_decimals: i64,
+}
+
+impl UniV2Router {
+    pub fn new(rpcs: SharedRpcPool, router: H160, path: Vec<H160>, quote_decimals: i64) -> Self {
+        Self {
+            rpcs,
+            router,
+            path,
+            quote_decimals,
+        }
+    }
+}
+
+impl PriceSource for UniV2Router {
+    fn price<'a>(&'a self, token: &'a Token) -> BoxFuture<'a, Result<Decimal, PriceError>> {
+        Box::pin(async move {
+            let token_address = parse_address(&token.contract_address)
+                .ok_or_else(|| PriceError::BadAmount(token.contract_address.clone()))?;
+            let hops = match self.path.iter().position(|hop| *hop == token_address) {
+                Some(position) => &self.path[position + 1..],
+                None => &self.path[..],
+            };
+            if hops.is_empty() {
+                // the token is the stablecoin itself
+                return Ok(Decimal::ONE);
+            }
+            let mut path = vec![token_address];
+            path.extend_from_slice(hops);
+            let unit =
+                decimals_unit(token.decimals).ok_or(PriceError::BadDecimals(token.decimals))?;
+            let amount_in = U256::from(unit.mantissa() as u128);
+
+            let router = self.router;
+            let abi = UNIV2_ROUTER_ABI.clone();
+            let amounts: Vec<U256> = self
+                .rpcs
+                .lock()
+                .await
+                .call(|web3| async move {
+                    Contract::new(web3.eth(), router, abi)
+                        .query(
+                            "getAmountsOut",
+                            (amount_in, path),
+                            None,
+                            Default::default(),
+                            None,
+                        )
+                        .await
+                        .map_err(|e| match e {
+                            web3::contract::Error::Api(e) => e,
+                            e => web3::Error::Decoder(e.to_string()),
+                        })
+                })
+                .await
+                .map_err(PriceError::Rpc)?;
+
+            let amount_out = amounts.last().ok_or(PriceError::NoPrice)?.to_string();
+            let amount_out = Decimal::from_str(&amount_out)
+                .map_err(|_| PriceError::BadAmount(amount_out.clone()))?;
+
+            let quote_unit = decimals_unit(self.quote_decimals)
+                .ok_or(PriceError::BadDecimals(self.quote_decimals))?;
+            Ok(amount_out / quote_unit)
+        })
+    }
+}
+
+/// Fixed price, meant for the stablecoins
+pub struct StaticPrice {
+    price: Decimal,
+}
+
+impl StaticPrice {
+    pub fn new(price: Decimal) -> Self {
+        Self { price }
+    }
+}
+
+impl PriceSource for StaticPrice {
+    fn price<'a>(&'a self, _token: &'a Token) -> BoxFuture<'a, Result<Decimal, PriceError>> {
+        Box::pin(async move { Ok(self.price) })
+    }
+}
+
+/// Median of the prices returned by the sources, failing sources are skipped
+pub struct MedianPrice {
+    sources: Vec<Box<dyn PriceSource>>,
+}
+
+impl MedianPrice {
+    pub fn new(sources: Vec<Box<dyn PriceSource>>) -> Self {
+        Self { sources }
+    }
+}
+
+impl PriceSource for MedianPrice {
+    fn price<'a>(&'a self, token: &'a Token) -> BoxFuture<'a, Result<Decimal, PriceError>> {
+        Box::pin(async move {
+            let mut prices: Vec<Decimal> =
+                join_all(self.sources.iter().map(|source| source.price(token)))
+                    .await
+                    .into_iter()
+                    .filter_map(|price| match price {
+                        Ok(price) => Some(price),
+                        Err(e) => {
+                            warn!("Price source failed for `{}`: {}", token.name, e);
+                            None
+                        }
+                    })
+                    .collect();
+
+            median(&mut prices).ok_or(PriceError::NoPrice)
+        })
+    }
+}
+
+fn median(prices: &mut [Decimal]) -> Option<Decimal> {
+    if prices.is_empty() {
+        return None;
+    }
+    prices.sort();
+    // both are the middle price for an odd amount of prices
+    let lower = prices[(prices.len() - 1) / 2];
+    let upper = prices[prices.len() / 2];
+    Some((lower + upper) / Decimal::from(2))
+}
+
+fn build_source(config: &PriceSourceConfig, rpcs: &SharedRpcPool) -> Option<Box<dyn PriceSource>> {
+    match config.kind.as_str() {
+        "univ2" => {
+            let router = parse_address(config.router_address.as_deref()?)?;
+            let path = config
+                .path
+                .as_deref()?
+                .split(' ')
+                .map(parse_address)
+                .collect::<Option<Vec<H160>>>()?;
+            let quote_decimals = config
+                .quote_decimals
+                .filter(|decimals| decimals_unit(*decimals).is_some())?;
+            Some(Box::new(UniV2Router::new(
+                rpcs.clone(),
+                router,
+                path,
+                quote_decimals,
+            )))
+        }
+        "static" => Some(Box::new(StaticPrice::new(Decimal::from_f64_retain(
+            config.price?,
+        )?))),
+        _ => None,
+    }
+}
+
+/// Builds the price source of the token out of the sources configured for it,
+/// falling back to the ones of the whole network,
+/// several sources are aggregated with the median
+pub fn token_price_source(
+    token: &Token,
+    configs: &[PriceSourceConfig],
+    rpcs: &SharedRpcPool,
+) -> Option<Box<dyn PriceSource>> {
+    let token_configs: Vec<&PriceSourceConfig> = configs
+        .iter()
+        .filter(|config| {
+            config
+                .token_address
+                .as_ref()
+                .is_some_and(|address| address.eq_ignore_ascii_case(&token.contract_address))
+        })
+        .collect();
+    let configs: Vec<&PriceSourceConfig> = if token_configs.is_empty() {
+        configs
+            .iter()
+            .filter(|config| config.token_address.is_none())
+            .collect()
+    } else {
+        token_configs
+    };
+
+    let mut sources: Vec<Box<dyn PriceSource>> = configs
+        .into_iter()
+        .filter_map(|config| {
+            let source = build_source(config, rpcs);
+            if source.is_none() {
+                warn!("Malformed price source {}", config.id);
+            }
+            source
+        })
+        .collect();
+    debug!("Token `{}` has {} price sources", token.name, sources.len());
+
+    match sources.len() {
+        0 => None,
+        1 => sources.pop(),
+        _ => Some(Box::new(MedianPrice::new(sources))),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn median_test() {
+        let mut prices = vec![Decimal::from(3), Decimal::from(1), Decimal::from(2)];
+        assert_eq!(median(&mut prices), Some(Decimal::from(2)));
+        prices.push(Decimal::from(10));
+        assert_eq!(median(&mut prices), Some(Decimal::new(25, 1)));
+        assert_eq!(median(&mut []), None);
+    }
+
+    #[test]
+    fn univ2_router_abi_test() {
+        assert!(UNIV2_ROUTER_ABI.function("getAmountsOut").is_ok());
+    }
+
+    #[test]
+    fn decimals_unit_test() {
+        assert_eq!(decimals_unit(0), Some(Decimal::ONE));
+        assert_eq!(decimals_unit(6), Some(Decimal::from(1_000_000)));
+        assert_eq!(
+            decimals_unit(MAX_DECIMALS),
+            Some(Decimal::from(10u128.pow(28)))
+        );
+        assert_eq!(decimals_unit(MAX_DECIMALS + 1), None);
+        assert_eq!(decimals_unit(-1), None);
+    }
+}
@@ -13,6 +13,13 @@ pub async fn handle_rejection(
         error!("Error: {:?}", e);
         match e {
             ApiError::DbError(_) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            ApiError::NotAdmin
+            | ApiError::TokenExpired
+            | ApiError::InvalidToken(_)
+            | ApiError::BadNonce
+            | ApiError::InvalidRefreshToken
+            | ApiError::RefreshTokenReused => (StatusCode::UNAUTHORIZED, e.to_string()),
+            ApiError::NotFound(..) => (StatusCode::NOT_FOUND, e.to_string()),
             _ => (StatusCode::BAD_REQUEST, e.to_string()),
         }
     } else {
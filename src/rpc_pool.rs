@@ -0,0 +1,250 @@
+use crate::models::json_responses::RpcHealth;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::{Mutex, RwLock};
+use tokio::time::{sleep_until, Duration, Instant};
+use tracing::{debug, error, warn};
+use web3::transports::Http;
+use web3::Web3;
+
+/// Latest health snapshot of the rpcs of every network
+pub type RpcHealthRegistry = Arc<RwLock<HashMap<i64, Vec<RpcHealth>>>>;
+
+/// Rpcs of a network shared between its handlers
+pub type SharedRpcPool = Arc<Mutex<RpcPool>>;
+
+/// Backoff applied after the first failed request to an rpc
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+/// Upper bound of the backoff, reached after several consecutive failures
+const MAX_BACKOFF: Duration = Duration::from_secs(120);
+/// Weight of the latest sample in the moving averages
+const SMOOTHING: f64 = 0.2;
+/// Score penalty for every block the rpc is behind the best one
+const LAG_PENALTY_MS: f64 = 1000.0;
+/// Score penalty for an rpc failing every request
+const ERROR_PENALTY_MS: f64 = 10000.0;
+
+struct RpcEndpoint {
+    url: String,
+    web3: Web3<Http>,
+    latency_ms: f64,
+    error_rate: f64,
+    requests: u64,
+    errors: u64,
+    consecutive_failures: u32,
+    block_number: Option<u64>,
+    block_lag: u64,
+    backoff_until: Option<Instant>,
+}
+
+impl RpcEndpoint {
+    fn new(url: String, web3: Web3<Http>) -> Self {
+        Self {
+            url,
+            web3,
+            latency_ms: 0.0,
+            error_rate: 0.0,
+            requests: 0,
+            errors: 0,
+            consecutive_failures: 0,
+            block_number: None,
+            block_lag: 0,
+            backoff_until: None,
+        }
+    }
+
+    /// Lower is better
+    fn score(&self) -> f64 {
+        self.latency_ms
+            + self.error_rate * ERROR_PENALTY_MS
+            + self.block_lag as f64 * LAG_PENALTY_MS
+    }
+
+    fn is_backing_off(&self, now: Instant) -> bool {
+        self.backoff_until.is_some_and(|until| until > now)
+    }
+
+    fn record(&mut self, latency: Duration, success: bool) {
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        self.latency_ms = if self.requests == 0 {
+            latency_ms
+        } else {
+            self.latency_ms * (1.0 - SMOOTHING) + latency_ms * SMOOTHING
+        };
+        self.requests += 1;
+
+        let failure = if success { 0.0 } else { 1.0 };
+        self.error_rate = self.error_rate * (1.0 - SMOOTHING) + failure * SMOOTHING;
+
+        if success {
+            self.consecutive_failures = 0;
+            self.backoff_until = None;
+        } else {
+            self.errors += 1;
+            self.consecutive_failures += 1;
+            let backoff = backoff_for(self.consecutive_failures);
+            warn!(
+                "Rpc `{}` failed {} times in a row, backing off for {:?}",
+                self.url, self.consecutive_failures, backoff
+            );
+            self.backoff_until = Some(Instant::now() + backoff);
+        }
+    }
+
+    fn health(&self, now: Instant) -> RpcHealth {
+        RpcHealth {
+            url: self.url.clone(),
+            latency_ms: self.latency_ms,
+            error_rate: self.error_rate,
+            requests: self.requests,
+            errors: self.errors,
+            block_number: self.block_number,
+            block_lag: self.block_lag,
+            backoff_ms: self
+                .backoff_until
+                .map(|until| until.saturating_duration_since(now).as_millis() as u64)
+                .unwrap_or(0),
+            score: self.score(),
+        }
+    }
+}
+
+fn backoff_for(consecutive_failures: u32) -> Duration {
+    BASE_BACKOFF
+        .saturating_mul(2u32.saturating_pow(consecutive_failures.saturating_sub(1)))
+        .min(MAX_BACKOFF)
+}
+
+/// Rpcs of a network, requests are routed to the healthiest one
+pub struct RpcPool {
+    network_id: i64,
+    endpoints: Vec<RpcEndpoint>,
+    registry: RpcHealthRegistry,
+}
+
+impl RpcPool {
+    pub fn new(network_id: i64, urls: &[String], registry: RpcHealthRegistry) -> Option<Self> {
+        let endpoints: Vec<RpcEndpoint> = urls
+            .iter()
+            .filter_map(|url| match Http::new(url) {
+                Ok(transport) => Some(RpcEndpoint::new(url.clone(), Web3::new(transport))),
+                Err(e) => {
+                    error!("Network {}: bad rpc `{}`: {:?}", network_id, url, e);
+                    None
+                }
+            })
+            .collect();
+        if endpoints.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            network_id,
+            endpoints,
+            registry,
+        })
+    }
+
+    /// Picks the rpc with the best score, waits for the nearest backoff
+    /// to expire if all of them are failing
+    async fn select(&self) -> usize {
+        let now = Instant::now();
+        let available = self
+            .endpoints
+            .iter()
+            .enumerate()
+            .filter(|(_, endpoint)| !endpoint.is_backing_off(now))
+            .min_by(|(_, a), (_, b)| a.score().total_cmp(&b.score()))
+            .map(|(index, _)| index);
+        if let Some(index) = available {
+            return index;
+        }
+
+        let (index, until) = self
+            .endpoints
+            .iter()
+            .enumerate()
+            .filter_map(|(index, endpoint)| endpoint.backoff_until.map(|until| (index, until)))
+            .min_by_key(|(_, until)| *until)
+            .unwrap();
+        debug!(
+            "Network {}: all rpcs are failing, waiting for `{}`",
+            self.network_id, self.endpoints[index].url
+        );
+        sleep_until(until).await;
+        index
+    }
+
+    /// Runs the request against the best rpc and records its outcome
+    pub async fn call<F, Fut, R>(&mut self, request: F) -> Result<R, web3::Error>
+    where
+        F: FnOnce(Web3<Http>) -> Fut,
+        Fut: Future<Output = Result<R, web3::Error>>,
+    {
+        let index = self.select().await;
+        let started = Instant::now();
+        let result = request(self.endpoints[index].web3.clone()).await;
+        self.endpoints[index].record(started.elapsed(), result.is_ok());
+        self.publish().await;
+        result
+    }
+
+    /// Polls the block height of every available rpc, returns the highest one
+    pub async fn update_heights(&mut self) -> Option<u64> {
+        let now = Instant::now();
+        for endpoint in self.endpoints.iter_mut() {
+            if endpoint.is_backing_off(now) {
+                continue;
+            }
+            let started = Instant::now();
+            let result = endpoint.web3.eth().block_number().await;
+            endpoint.record(started.elapsed(), result.is_ok());
+            match result {
+                Ok(number) => endpoint.block_number = Some(number.as_u64()),
+                Err(e) => error!(
+                    "Network {}: error getting block number from `{}`: {:?}",
+                    self.network_id, endpoint.url, e
+                ),
+            }
+        }
+
+        let head = self
+            .endpoints
+            .iter()
+            .filter_map(|endpoint| endpoint.block_number)
+            .max();
+        if let Some(head) = head {
+            for endpoint in self.endpoints.iter_mut() {
+                endpoint.block_lag = endpoint
+                    .block_number
+                    .map(|number| head.saturating_sub(number))
+                    .unwrap_or(0);
+            }
+        }
+        self.publish().await;
+        head
+    }
+
+    async fn publish(&self) {
+        let now = Instant::now();
+        let health = self
+            .endpoints
+            .iter()
+            .map(|endpoint| endpoint.health(now))
+            .collect();
+        self.registry.write().await.insert(self.network_id, health);
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_test() {
+        assert_eq!(backoff_for(1), BASE_BACKOFF);
+        assert_eq!(backoff_for(3), BASE_BACKOFF * 4);
+        assert_eq!(backoff_for(100), MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,334 @@
+use std::str::{FromStr, Lines};
+
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+use web3::signing::keccak256;
+
+const HEADER_SUFFIX: &str = " wants you to sign in with your Ethereum account:";
+
+#[derive(Error, Debug, PartialEq)]
+pub enum SiweError {
+    #[error("Missing or malformed `{0}`")]
+    Field(&'static str),
+
+    #[error("Address `{0}` isn't EIP-55 checksummed")]
+    Checksum(String),
+
+    #[error("Unsupported version `{0}`")]
+    Version(String),
+
+    #[error("The message is for `{0}`")]
+    Domain(String),
+
+    #[error("The message is for the uri `{0}`")]
+    Uri(String),
+
+    #[error("The message is for the scheme `{0}`")]
+    Scheme(String),
+
+    #[error("Unsupported chain `{0}`")]
+    Chain(u64),
+
+    #[error("The message has expired")]
+    Expired,
+
+    #[error("The message isn't valid yet")]
+    NotYetValid,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SiweMessage {
+    pub scheme: Option<String>,
+    pub domain: String,
+    /// EIP-55 checksummed address
+    pub address: String,
+    pub statement: Option<String>,
+    pub uri: String,
+    pub version: String,
+    pub chain_id: u64,
+    pub nonce: String,
+    pub issued_at: DateTime<Utc>,
+    pub expiration_time: Option<DateTime<Utc>>,
+    pub not_before: Option<DateTime<Utc>>,
+    pub request_id: Option<String>,
+    pub resources: Vec<String>,
+}
+
+impl SiweMessage {
+    /// Checks the message is meant for this server and is valid at `now`, the uri must
+    /// be under the `origin` of the dapp, the chain, nonce and signature are checked by the caller
+    pub fn validate(
+        &self,
+        domain: &str,
+        origin: &str,
+        now: DateTime<Utc>,
+    ) -> Result<(), SiweError> {
+        if self.domain != domain {
+            return Err(SiweError::Domain(self.domain.clone()));
+        }
+        if let Some(scheme) = &self.scheme {
+            if origin.split_once("://").map(|(scheme, _)| scheme) != Some(scheme.as_str()) {
+                return Err(SiweError::Scheme(scheme.clone()));
+            }
+        }
+        let under_origin = self
+            .uri
+            .strip_prefix(origin)
+            .is_some_and(|path| path.is_empty() || path.starts_with('/') || path.starts_with('?'));
+        if !under_origin {
+            return Err(SiweError::Uri(self.uri.clone()));
+        }
+        if self.version != "1" {
+            return Err(SiweError::Version(self.version.clone()));
+        }
+        if self.expiration_time.is_some_and(|time| time <= now) {
+            return Err(SiweError::Expired);
+        }
+        if self.not_before.is_some_and(|time| time > now) {
+            return Err(SiweError::NotYetValid);
+        }
+
+        Ok(())
+    }
+}
+
+fn tagged<'a>(lines: &mut Lines<'a>, tag: &'static str) -> Result<&'a str, SiweError> {
+    lines
+        .next()
+        .and_then(|line| line.strip_prefix(tag))
+        .and_then(|line| line.strip_prefix(": "))
+        .ok_or(SiweError::Field(tag))
+}
+
+fn optional_tagged<'a>(lines: &mut Lines<'a>, tag: &'static str) -> Option<&'a str> {
+    let value = lines
+        .clone()
+        .next()
+        .and_then(|line| line.strip_prefix(tag))
+        .and_then(|line| line.strip_prefix(": "))?;
+    lines.next();
+    Some(value)
+}
+
+fn timestamp(value: &str, tag: &'static str) -> Result<DateTime<Utc>, SiweError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| SiweError::Field(tag))
+}
+
+/// EIP-55 mixed case encoding of the address
+pub fn checksum_address(address: &str) -> String {
+    let address = address.trim_start_matches("0x").to_lowercase();
+    let hash = keccak256(address.as_bytes());
+    let checksummed: String = address
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let byte = hash[i / 2];
+            let nibble = if i % 2 == 0 { byte >> 4 } else { byte & 0x0f };
+            if nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect();
+    format!("0x{}", checksummed)
+}
+
+impl FromStr for SiweMessage {
+    type Err = SiweError;
+
+    fn from_str(message: &str) -> Result<Self, Self::Err> {
+        let mut lines = message.lines();
+
+        let header = lines
+            .next()
+            .and_then(|line| line.strip_suffix(HEADER_SUFFIX))
+            .ok_or(SiweError::Field("domain"))?;
+        let (scheme, domain) = match header.split_once("://") {
+            Some((scheme, domain)) => (Some(scheme.to_owned()), domain),
+            None => (None, header),
+        };
+        if domain.is_empty() {
+            return Err(SiweError::Field("domain"));
+        }
+
+        let address = lines
+            .next()
+            .filter(|address| {
+                address.len() == 42
+                    && address.starts_with("0x")
+                    && address[2..].chars().all(|c| c.is_ascii_hexdigit())
+            })
+            .ok_or(SiweError::Field("address"))?;
+        if checksum_address(address) != address {
+            return Err(SiweError::Checksum(address.to_owned()));
+        }
+
+        if lines.next() != Some("") {
+            return Err(SiweError::Field("statement"));
+        }
+        let statement = match lines.next() {
+            Some("") => None,
+            Some(statement) => {
+                if lines.next() != Some("") {
+                    return Err(SiweError::Field("statement"));
+                }
+                Some(statement.to_owned())
+            }
+            None => return Err(SiweError::Field("URI")),
+        };
+
+        let uri = tagged(&mut lines, "URI")?.to_owned();
+        let version = tagged(&mut lines, "Version")?.to_owned();
+        let chain_id = tagged(&mut lines, "Chain ID")?
+            .parse()
+            .map_err(|_| SiweError::Field("Chain ID"))?;
+        let nonce = tagged(&mut lines, "Nonce")?;
+        if nonce.len() < 8 || !nonce.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(SiweError::Field("Nonce"));
+        }
+        let issued_at = timestamp(tagged(&mut lines, "Issued At")?, "Issued At")?;
+        let expiration_time = optional_tagged(&mut lines, "Expiration Time")
+            .map(|time| timestamp(time, "Expiration Time"))
+            .transpose()?;
+        let not_before = optional_tagged(&mut lines, "Not Before")
+            .map(|time| timestamp(time, "Not Before"))
+            .transpose()?;
+        let request_id = optional_tagged(&mut lines, "Request ID").map(str::to_owned);
+
+        let mut resources = Vec::new();
+        if let Some(line) = lines.next() {
+            if line != "Resources:" {
+                return Err(SiweError::Field("Resources"));
+            }
+            for line in lines {
+                let resource = line
+                    .strip_prefix("- ")
+                    .ok_or(SiweError::Field("Resources"))?;
+                resources.push(resource.to_owned());
+            }
+        }
+
+        Ok(Self {
+            scheme,
+            domain: domain.to_owned(),
+            address: address.to_owned(),
+            statement,
+            uri,
+            version,
+            chain_id,
+            nonce: nonce.to_owned(),
+            issued_at,
+            expiration_time,
+            not_before,
+            request_id,
+            resources,
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    const MESSAGE: &str = "\
+bicas.io wants you to sign in with your Ethereum account:
+0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed
+
+Sign in to BICAS
+
+URI: https://bicas.io/login
+Version: 1
+Chain ID: 56
+Nonce: 32891756abcd
+Issued At: 2023-10-31T16:25:24Z
+Expiration Time: 2023-10-31T16:30:24Z
+Resources:
+- https://bicas.io/terms";
+
+    #[test]
+    fn parse_message_test() {
+        let message: SiweMessage = MESSAGE.parse().unwrap();
+        assert_eq!(message.domain, "bicas.io");
+        assert_eq!(
+            message.address,
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"
+        );
+        assert_eq!(message.statement.as_deref(), Some("Sign in to BICAS"));
+        assert_eq!(message.chain_id, 56);
+        assert_eq!(message.nonce, "32891756abcd");
+        assert_eq!(message.not_before, None);
+        assert_eq!(message.resources, vec!["https://bicas.io/terms"]);
+
+        let issued_at = message.issued_at;
+        assert_eq!(
+            message.validate("bicas.io", "https://bicas.io", issued_at),
+            Ok(())
+        );
+        assert_eq!(
+            message.validate("evil.io", "https://bicas.io", issued_at),
+            Err(SiweError::Domain("bicas.io".into()))
+        );
+        assert_eq!(
+            message.validate("bicas.io", "https://bicas.io.evil", issued_at),
+            Err(SiweError::Uri("https://bicas.io/login".into()))
+        );
+        assert_eq!(
+            message.validate("bicas.io", "https://bicas", issued_at),
+            Err(SiweError::Uri("https://bicas.io/login".into()))
+        );
+        assert_eq!(
+            message.validate(
+                "bicas.io",
+                "https://bicas.io",
+                message.expiration_time.unwrap()
+            ),
+            Err(SiweError::Expired)
+        );
+
+        let message: SiweMessage = MESSAGE
+            .replacen("bicas.io", "http://bicas.io", 1)
+            .parse()
+            .unwrap();
+        assert_eq!(message.scheme.as_deref(), Some("http"));
+        assert_eq!(
+            message.validate("bicas.io", "https://bicas.io", issued_at),
+            Err(SiweError::Scheme("http".into()))
+        );
+    }
+
+    #[test]
+    fn parse_message_without_statement_test() {
+        let message = MESSAGE.replace("Sign in to BICAS\n", "");
+        let message: SiweMessage = message.parse().unwrap();
+        assert_eq!(message.statement, None);
+        assert_eq!(message.uri, "https://bicas.io/login");
+    }
+
+    #[test]
+    fn parse_bad_message_test() {
+        assert_eq!(
+            MESSAGE
+                .replace(
+                    "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+                    "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"
+                )
+                .parse::<SiweMessage>(),
+            Err(SiweError::Checksum(
+                "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed".into()
+            ))
+        );
+        assert_eq!(
+            MESSAGE
+                .replace("Nonce: 32891756abcd", "Nonce: 123")
+                .parse::<SiweMessage>(),
+            Err(SiweError::Field("Nonce"))
+        );
+        assert_eq!(
+            MESSAGE.replace("Version: 1\n", "").parse::<SiweMessage>(),
+            Err(SiweError::Field("Version"))
+        );
+    }
+}
@@ -1,16 +1,54 @@
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
 use blake2::{Blake2b512, Digest};
 use hex::ToHex;
-use jwt::Error as JwtError;
+use rand::rngs::OsRng;
 use web3::signing::{keccak256, recover};
 
-use crate::jwt::{verify_token, Payload};
-
 pub fn blake_hash(message: &str) -> String {
     let mut hasher = Blake2b512::new();
     hasher.update(message.as_bytes());
     hasher.finalize().encode_hex()
 }
 
+/// Salted Argon2id hash of the password in the PHC string format, computed
+/// on the blocking pool so the slow hashing doesn't stall the runtime
+pub async fn hash_password(password: &str) -> String {
+    let password = password.to_owned();
+    tokio::task::spawn_blocking(move || {
+        Argon2::default()
+            .hash_password(password.as_bytes(), &SaltString::generate(&mut OsRng))
+            .unwrap()
+            .to_string()
+    })
+    .await
+    .unwrap()
+}
+
+/// Checks the password against a PHC hash or a legacy unsalted Blake2b one,
+/// on the blocking pool as well
+pub async fn verify_password(password: &str, hash: &str) -> bool {
+    let (password, hash) = (password.to_owned(), hash.to_owned());
+    tokio::task::spawn_blocking(move || match PasswordHash::new(&hash) {
+        Ok(hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok(),
+        Err(_) => blake_hash(&password) == hash,
+    })
+    .await
+    .unwrap()
+}
+
+/// Legacy Blake2b hashes get replaced on the next successful login
+pub fn is_legacy_password_hash(hash: &str) -> bool {
+    PasswordHash::new(hash).map_or(true, |hash| hash.algorithm != argon2::ARGON2ID_IDENT)
+}
+
+/// Random hex encoded 256 bit secret
+pub fn random_token() -> String {
+    rand::random::<[u8; 32]>().encode_hex()
+}
+
 pub fn hash_message(message: &str) -> [u8; 32] {
     keccak256(
         format!(
@@ -33,6 +71,10 @@ pub fn verify_signature(pub_key: &str, message: &str, signature: &str) -> bool {
         }
     };
 
+    if signature.len() != 65 {
+        return false;
+    }
+
     let recovery_id = signature[64] as i32 - 27;
 
     let calculated_pubkey = match recover(&message_hash, &signature[..64], recovery_id) {
@@ -47,10 +89,6 @@ pub fn verify_signature(pub_key: &str, message: &str, signature: &str) -> bool {
     pub_key.eq(&calculated_pubkey)
 }
 
-pub fn serialize_token(input: &str, key: &str) -> Result<Payload, JwtError> {
-    verify_token(input, key)
-}
-
 #[cfg(test)]
 pub mod tests {
     use super::*;
@@ -59,4 +97,19 @@ pub mod tests {
     fn verify_signature_test() {
         assert!(verify_signature(&"0x67adcf8c25c88af0df3cab522c9dd5b11d017aca".to_lowercase(), "SewerTT", "c4dfdf84509168530464833260da05f45bc680c188c5c1eff59010b0c6c6c6d00c74e442cfa4cd3e67d70a89fdaba67dcc8eec9ebc8716504cc02b6bd89bb8641c"))
     }
+
+    #[tokio::test]
+    async fn verify_password_test() {
+        let hash = hash_password("password").await;
+        assert!(hash.starts_with("$argon2id$"));
+        assert_ne!(hash, hash_password("password").await);
+        assert!(verify_password("password", &hash).await);
+        assert!(!verify_password("passwordd", &hash).await);
+        assert!(!is_legacy_password_hash(&hash));
+
+        let legacy = blake_hash("password");
+        assert!(verify_password("password", &legacy).await);
+        assert!(!verify_password("passwordd", &legacy).await);
+        assert!(is_legacy_password_hash(&legacy));
+    }
 }